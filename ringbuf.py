@@ -5,16 +5,146 @@
 # <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 # option. This file may not be copied, modified, or distributed
 # except according to those terms.
+import array
+import collections
 import ctypes
 import ctypes.util
+import enum
 import functools
+import os
 import threading
 
 _rb = ctypes.cdll.LoadLibrary(ctypes.util.find_library("ringbuf"))
 
+class OverflowPolicy(enum.IntEnum):
+    """
+    What to do when pushing more data than the buffer has room for.
+    """
+    # Drop the oldest bytes to make room for the new ones.
+    OVERWRITE_OLDEST = 0
+    # Raise `BufferFullError` without storing anything.
+    REJECT = 1
+    # Store as many of the new bytes as fit, and drop the rest.
+    TRUNCATE_INCOMING = 2
+    # Keep the old bytes, and drop all of the new ones.
+    DROP_INCOMING = 3
+
+class ResizePolicy(enum.IntEnum):
+    """
+    Which elements to keep when shrinking a buffer below its length.
+    """
+    # Drop the oldest elements.
+    KEEP_NEWEST = 0
+    # Drop the newest elements.
+    KEEP_OLDEST = 1
+
+class BufferFullError(Exception):
+    """
+    Raised when pushing more data than fits into a buffer using `OverflowPolicy.REJECT`.
+    """
+
+class ShutdownError(Exception):
+    """
+    Raised when waiting on a thread-safe buffer which was shut down.
+    """
+
+class EvictedError(Exception):
+    """
+    Raised when accessing a stream position which is not in the buffer anymore.
+    """
+
+PushResult = collections.namedtuple('PushResult', ('stored', 'dropped'))
+
+class _Pushed(ctypes.Structure):
+    _fields_ = (('stored', ctypes.c_size_t), ('dropped', ctypes.c_size_t),)
+
+class _Stats(ctypes.Structure):
+    _fields_ = (
+        ('pushed', ctypes.c_uint64),
+        ('consumed', ctypes.c_uint64),
+        ('overwritten', ctypes.c_uint64),
+        ('dropped', ctypes.c_uint64),
+        ('overflows', ctypes.c_uint64),
+        ('high_water_mark', ctypes.c_uint64),
+    )
+
+class _Slices(ctypes.Structure):
+    _fields_ = (
+        ('first', ctypes.c_void_p),
+        ('first_len', ctypes.c_size_t),
+        ('second', ctypes.c_void_p),
+        ('second_len', ctypes.c_size_t),
+    )
+
+Stats = collections.namedtuple('Stats', tuple(name for name, _ in _Stats._fields_))
+
+_rb_last_error = _rb.last_error
+_rb_last_error.argtypes = ()
+_rb_last_error.restype = ctypes.c_char_p
+
 _rb_new = _rb.new
-_rb_new.argtypes = (ctypes.c_size_t,)
-_rb_new.restype = ctypes.c_void_p
+_rb_new.argtypes = (ctypes.c_size_t, ctypes.POINTER(ctypes.c_void_p),)
+_rb_new.restype = ctypes.c_int
+
+_rb_new_with_policy = _rb.new_with_policy
+_rb_new_with_policy.argtypes = (ctypes.c_size_t, ctypes.c_uint32, ctypes.POINTER(ctypes.c_void_p),)
+_rb_new_with_policy.restype = ctypes.c_int
+
+_rb_new_mirrored = _rb.new_mirrored
+_rb_new_mirrored.argtypes = (ctypes.c_size_t, ctypes.POINTER(ctypes.c_void_p),)
+_rb_new_mirrored.restype = ctypes.c_int
+
+_rb_create_file = _rb.create_file
+_rb_create_file.argtypes = (ctypes.c_char_p, ctypes.c_size_t, ctypes.c_uint32, ctypes.POINTER(ctypes.c_void_p),)
+_rb_create_file.restype = ctypes.c_int
+
+_rb_open_file = _rb.open_file
+_rb_open_file.argtypes = (ctypes.c_char_p, ctypes.POINTER(ctypes.c_void_p),)
+_rb_open_file.restype = ctypes.c_int
+
+_rb_is_mirrored = _rb.is_mirrored
+_rb_is_mirrored.argtypes = (ctypes.c_void_p,)
+_rb_is_mirrored.restype = ctypes.c_bool
+
+_rb_overflow_policy = _rb.overflow_policy
+_rb_overflow_policy.argtypes = (ctypes.c_void_p,)
+_rb_overflow_policy.restype = ctypes.c_int
+
+_rb_set_overflow_policy = _rb.set_overflow_policy
+_rb_set_overflow_policy.argtypes = (ctypes.c_void_p, ctypes.c_uint32,)
+_rb_set_overflow_policy.restype = ctypes.c_int
+
+_rb_stats = _rb.stats
+_rb_stats.argtypes = (ctypes.c_void_p, ctypes.POINTER(_Stats),)
+_rb_stats.restype = ctypes.c_int
+
+_rb_reset_stats = _rb.reset_stats
+_rb_reset_stats.argtypes = (ctypes.c_void_p,)
+_rb_reset_stats.restype = ctypes.c_int
+
+_rb_flush = _rb.flush
+_rb_flush.argtypes = (ctypes.c_void_p,)
+_rb_flush.restype = ctypes.c_int
+
+_rb_serialized_len = _rb.serialized_len
+_rb_serialized_len.argtypes = (ctypes.c_void_p,)
+_rb_serialized_len.restype = ctypes.c_size_t
+
+_rb_serialize = _rb.serialize
+_rb_serialize.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_size_t),)
+_rb_serialize.restype = ctypes.c_int
+
+_rb_deserialize = _rb.deserialize
+_rb_deserialize.argtypes = (ctypes.c_char_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_void_p),)
+_rb_deserialize.restype = ctypes.c_int
+
+_rb_capacity = _rb.capacity
+_rb_capacity.argtypes = (ctypes.c_void_p,)
+_rb_capacity.restype = ctypes.c_size_t
+
+_rb_resize = _rb.resize
+_rb_resize.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.c_uint32, ctypes.POINTER(ctypes.c_size_t),)
+_rb_resize.restype = ctypes.c_int
 
 _rb_read_available = _rb.read_available
 _rb_read_available.argtypes = (ctypes.c_void_p,)
@@ -25,22 +155,359 @@ _rb_write_available.argtypes = (ctypes.c_void_p,)
 _rb_write_available.restype = ctypes.c_size_t
 
 _rb_peek = _rb.peek
-_rb_peek.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
-_rb_peek.restype = ctypes.POINTER(ctypes.c_uint8)
+_rb_peek.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_void_p),)
+_rb_peek.restype = ctypes.c_int
+
+_rb_as_slices = _rb.as_slices
+_rb_as_slices.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(_Slices),)
+_rb_as_slices.restype = ctypes.c_int
+
+_rb_peek_into = _rb.peek_into
+_rb_peek_into.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_size_t),)
+_rb_peek_into.restype = ctypes.c_int
+
+_rb_read_position = _rb.read_position
+_rb_read_position.argtypes = (ctypes.c_void_p,)
+_rb_read_position.restype = ctypes.c_uint64
+
+_rb_write_position = _rb.write_position
+_rb_write_position.argtypes = (ctypes.c_void_p,)
+_rb_write_position.restype = ctypes.c_uint64
+
+_rb_peek_at_position = _rb.peek_at_position
+_rb_peek_at_position.argtypes = (ctypes.c_void_p, ctypes.c_uint64, ctypes.c_void_p, ctypes.c_size_t,)
+_rb_peek_at_position.restype = ctypes.c_int
+
+_rb_peek_at = _rb.peek_at
+_rb_peek_at.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.c_void_p, ctypes.c_size_t,)
+_rb_peek_at.restype = ctypes.c_int
+
+_rb_get = _rb.get
+_rb_get.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.c_void_p,)
+_rb_get.restype = ctypes.c_int
+
+_rb_find = _rb.find
+_rb_find.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_size_t),)
+_rb_find.restype = ctypes.c_int
+
+_rb_pop_until = _rb.pop_until
+_rb_pop_until.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t, ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_size_t),)
+_rb_pop_until.restype = ctypes.c_int
+
+_rb_pop_exact = _rb.pop_exact
+_rb_pop_exact.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t,)
+_rb_pop_exact.restype = ctypes.c_int
+
+_rb_pop = _rb.pop
+_rb_pop.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_size_t),)
+_rb_pop.restype = ctypes.c_int
 
 _rb_skip = _rb.skip
 _rb_skip.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
+_rb_skip.restype = ctypes.c_int
+
+_rb_retention = _rb.retention
+_rb_retention.argtypes = (ctypes.c_void_p,)
+_rb_retention.restype = ctypes.c_size_t
+
+_rb_set_retention = _rb.set_retention
+_rb_set_retention.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
+_rb_set_retention.restype = ctypes.c_int
+
+_rb_retained = _rb.retained
+_rb_retained.argtypes = (ctypes.c_void_p,)
+_rb_retained.restype = ctypes.c_size_t
+
+_rb_rewind_read = _rb.rewind_read
+_rb_rewind_read.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
+_rb_rewind_read.restype = ctypes.c_int
+
+_rb_mark = _rb.mark
+_rb_mark.argtypes = (ctypes.c_void_p, ctypes.c_char_p,)
+_rb_mark.restype = ctypes.c_int
+
+_rb_remove_mark = _rb.remove_mark
+_rb_remove_mark.argtypes = (ctypes.c_void_p, ctypes.c_char_p, ctypes.POINTER(ctypes.c_uint64),)
+_rb_remove_mark.restype = ctypes.c_int
+
+_rb_seek_to_mark = _rb.seek_to_mark
+_rb_seek_to_mark.argtypes = (ctypes.c_void_p, ctypes.c_char_p,)
+_rb_seek_to_mark.restype = ctypes.c_int
 
 _rb_push = _rb.push
-_rb_push.argtypes = (ctypes.c_void_p, ctypes.POINTER(ctypes.c_uint8), ctypes.c_size_t,)
+_rb_push.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(_Pushed),)
+_rb_push.restype = ctypes.c_int
+
+_rb_reserve = _rb.reserve
+_rb_reserve.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(_Slices),)
+_rb_reserve.restype = ctypes.c_int
+
+_rb_commit = _rb.commit
+_rb_commit.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
+_rb_commit.restype = ctypes.c_int
 
 _rb_del = getattr(_rb, 'del')
 _rb_del.argtypes = (ctypes.c_void_p,)
+_rb_del.restype = ctypes.c_int
+
+_rb_sync_new = _rb.sync_new
+_rb_sync_new.argtypes = (ctypes.c_void_p, ctypes.POINTER(ctypes.c_void_p),)
+_rb_sync_new.restype = ctypes.c_int
+
+_rb_sync_peek = _rb.sync_peek
+_rb_sync_peek.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t,)
+_rb_sync_peek.restype = ctypes.c_int
+
+_rb_sync_wait_readable = _rb.sync_wait_readable
+_rb_sync_wait_readable.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.c_int64, ctypes.POINTER(ctypes.c_size_t),)
+_rb_sync_wait_readable.restype = ctypes.c_int
+
+_rb_sync_wait_writable = _rb.sync_wait_writable
+_rb_sync_wait_writable.argtypes = (ctypes.c_void_p, ctypes.c_size_t, ctypes.c_int64, ctypes.POINTER(ctypes.c_size_t),)
+_rb_sync_wait_writable.restype = ctypes.c_int
+
+_rb_sync_shutdown = _rb.sync_shutdown
+_rb_sync_shutdown.argtypes = (ctypes.c_void_p,)
+_rb_sync_shutdown.restype = ctypes.c_int
+
+ElementType = collections.namedtuple('ElementType', ('ctype', 'typecode', 'suffix'))
+
+# The element types supported by `RingBuffer`, with the `array` typecode used to return them
+# and the suffix of their functions in the library.
+ELEMENT_TYPES = {
+    'u8': ElementType(ctypes.c_uint8, 'B', ''),
+    'i16': ElementType(ctypes.c_int16, 'h', '_i16'),
+    'i32': ElementType(ctypes.c_int32, next(code for code in 'il' if array.array(code).itemsize == 4), '_i32'),
+    'f32': ElementType(ctypes.c_float, 'f', '_f32'),
+    'f64': ElementType(ctypes.c_double, 'd', '_f64'),
+}
+
+def _variant(function, prefix, suffix):
+    variant = getattr(_rb, prefix + function.__name__ + suffix)
+    variant.argtypes = function.argtypes
+    variant.restype = function.restype
+    return variant
+
+# The functions which take the same arguments for both kinds of buffer.
+_SHARED_FUNCTIONS = (
+    _rb_overflow_policy,
+    _rb_set_overflow_policy,
+    _rb_stats,
+    _rb_reset_stats,
+    _rb_flush,
+    _rb_serialized_len,
+    _rb_serialize,
+    _rb_capacity,
+    _rb_resize,
+    _rb_read_available,
+    _rb_write_available,
+    _rb_peek_into,
+    _rb_read_position,
+    _rb_write_position,
+    _rb_peek_at_position,
+    _rb_peek_at,
+    _rb_get,
+    _rb_find,
+    _rb_pop_until,
+    _rb_pop_exact,
+    _rb_pop,
+    _rb_skip,
+    _rb_retention,
+    _rb_set_retention,
+    _rb_retained,
+    _rb_rewind_read,
+    _rb_mark,
+    _rb_remove_mark,
+    _rb_seek_to_mark,
+    _rb_push,
+    _rb_del,
+)
+
+# The functions of each element type, by name.
+_FUNCTIONS = {dtype: {function.__name__: _variant(function, '', element.suffix) for function in _SHARED_FUNCTIONS + (
+    _rb_new_with_policy,
+    _rb_new_mirrored,
+    _rb_deserialize,
+    _rb_is_mirrored,
+    _rb_peek,
+    _rb_as_slices,
+    _rb_reserve,
+    _rb_commit,
+    _rb_sync_new,
+    _rb_sync_peek,
+    _rb_sync_wait_readable,
+    _rb_sync_wait_writable,
+    _rb_sync_shutdown,
+)} for dtype, element in ELEMENT_TYPES.items()}
+
+# The thread-safe equivalent of the shared functions of each element type, by name.
+_SYNC_FUNCTIONS = {
+    dtype: {function.__name__: _variant(function, 'sync_', element.suffix) for function in _SHARED_FUNCTIONS}
+    for dtype, element in ELEMENT_TYPES.items()
+}
+
+_rb_spsc_new = _rb.spsc_new
+_rb_spsc_new.argtypes = (ctypes.c_size_t, ctypes.c_uint32, ctypes.POINTER(ctypes.c_void_p), ctypes.POINTER(ctypes.c_void_p),)
+_rb_spsc_new.restype = ctypes.c_int
+
+_rb_spsc_write_available = _rb.spsc_write_available
+_rb_spsc_write_available.argtypes = (ctypes.c_void_p,)
+_rb_spsc_write_available.restype = ctypes.c_size_t
+
+_rb_spsc_push = _rb.spsc_push
+_rb_spsc_push.argtypes = (ctypes.c_void_p, ctypes.c_char_p, ctypes.c_size_t, ctypes.POINTER(_Pushed),)
+_rb_spsc_push.restype = ctypes.c_int
+
+_rb_spsc_producer_del = _rb.spsc_producer_del
+_rb_spsc_producer_del.argtypes = (ctypes.c_void_p,)
+_rb_spsc_producer_del.restype = ctypes.c_int
+
+_rb_spsc_read_available = _rb.spsc_read_available
+_rb_spsc_read_available.argtypes = (ctypes.c_void_p,)
+_rb_spsc_read_available.restype = ctypes.c_size_t
+
+_rb_spsc_peek = _rb.spsc_peek
+_rb_spsc_peek.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t,)
+_rb_spsc_peek.restype = ctypes.c_int
+
+_rb_spsc_skip = _rb.spsc_skip
+_rb_spsc_skip.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
+_rb_spsc_skip.restype = ctypes.c_int
+
+_rb_spsc_consumer_del = _rb.spsc_consumer_del
+_rb_spsc_consumer_del.argtypes = (ctypes.c_void_p,)
+_rb_spsc_consumer_del.restype = ctypes.c_int
+
+_rb_record_new = _rb.record_new
+_rb_record_new.argtypes = (ctypes.c_size_t, ctypes.c_uint32, ctypes.POINTER(ctypes.c_void_p),)
+_rb_record_new.restype = ctypes.c_int
+
+_rb_record_count = _rb.record_count
+_rb_record_count.argtypes = (ctypes.c_void_p,)
+_rb_record_count.restype = ctypes.c_size_t
+
+_rb_record_push = _rb.record_push
+_rb_record_push.argtypes = (ctypes.c_void_p, ctypes.c_char_p, ctypes.c_size_t, ctypes.POINTER(_Pushed),)
+_rb_record_push.restype = ctypes.c_int
+
+_rb_record_peek = _rb.record_peek
+_rb_record_peek.argtypes = (ctypes.c_void_p, ctypes.POINTER(ctypes.c_void_p), ctypes.POINTER(ctypes.c_size_t),)
+_rb_record_peek.restype = ctypes.c_int
+
+_rb_record_skip = _rb.record_skip
+_rb_record_skip.argtypes = (ctypes.c_void_p, ctypes.POINTER(ctypes.c_size_t),)
+_rb_record_skip.restype = ctypes.c_int
+
+_rb_record_del = _rb.record_del
+_rb_record_del.argtypes = (ctypes.c_void_p,)
+_rb_record_del.restype = ctypes.c_int
+
+_rb_broadcast_new = _rb.broadcast_new
+_rb_broadcast_new.argtypes = (ctypes.c_size_t, ctypes.c_uint32, ctypes.POINTER(ctypes.c_void_p),)
+_rb_broadcast_new.restype = ctypes.c_int
+
+_rb_broadcast_reader_new = _rb.broadcast_reader_new
+_rb_broadcast_reader_new.argtypes = (ctypes.c_void_p, ctypes.POINTER(ctypes.c_void_p),)
+_rb_broadcast_reader_new.restype = ctypes.c_int
+
+_rb_broadcast_write_available = _rb.broadcast_write_available
+_rb_broadcast_write_available.argtypes = (ctypes.c_void_p,)
+_rb_broadcast_write_available.restype = ctypes.c_size_t
+
+_rb_broadcast_push = _rb.broadcast_push
+_rb_broadcast_push.argtypes = (ctypes.c_void_p, ctypes.c_char_p, ctypes.c_size_t, ctypes.POINTER(_Pushed),)
+_rb_broadcast_push.restype = ctypes.c_int
+
+_rb_broadcast_writer_del = _rb.broadcast_writer_del
+_rb_broadcast_writer_del.argtypes = (ctypes.c_void_p,)
+_rb_broadcast_writer_del.restype = ctypes.c_int
+
+_rb_broadcast_read_available = _rb.broadcast_read_available
+_rb_broadcast_read_available.argtypes = (ctypes.c_void_p, ctypes.POINTER(ctypes.c_size_t),)
+_rb_broadcast_read_available.restype = ctypes.c_int
+
+_rb_broadcast_peek = _rb.broadcast_peek
+_rb_broadcast_peek.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t,)
+_rb_broadcast_peek.restype = ctypes.c_int
+
+_rb_broadcast_skip = _rb.broadcast_skip
+_rb_broadcast_skip.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
+_rb_broadcast_skip.restype = ctypes.c_int
+
+_rb_broadcast_resync = _rb.broadcast_resync
+_rb_broadcast_resync.argtypes = (ctypes.c_void_p, ctypes.POINTER(ctypes.c_uint64),)
+_rb_broadcast_resync.restype = ctypes.c_int
+
+_rb_broadcast_reader_del = _rb.broadcast_reader_del
+_rb_broadcast_reader_del.argtypes = (ctypes.c_void_p,)
+_rb_broadcast_reader_del.restype = ctypes.c_int
+
+_rb_shm_ring_create = _rb.shm_ring_create
+_rb_shm_ring_create.argtypes = (ctypes.c_char_p, ctypes.c_size_t, ctypes.c_uint32, ctypes.POINTER(ctypes.c_void_p),)
+_rb_shm_ring_create.restype = ctypes.c_int
+
+_rb_shm_ring_open = _rb.shm_ring_open
+_rb_shm_ring_open.argtypes = (ctypes.c_char_p, ctypes.POINTER(ctypes.c_void_p),)
+_rb_shm_ring_open.restype = ctypes.c_int
+
+_rb_shm_ring_unlink = _rb.shm_ring_unlink
+_rb_shm_ring_unlink.argtypes = (ctypes.c_char_p,)
+_rb_shm_ring_unlink.restype = ctypes.c_int
+
+_rb_shm_ring_capacity = _rb.shm_ring_capacity
+_rb_shm_ring_capacity.argtypes = (ctypes.c_void_p,)
+_rb_shm_ring_capacity.restype = ctypes.c_size_t
+
+_rb_shm_ring_stats = _rb.shm_ring_stats
+_rb_shm_ring_stats.argtypes = (ctypes.c_void_p, ctypes.POINTER(_Stats),)
+_rb_shm_ring_stats.restype = ctypes.c_int
+
+_rb_shm_ring_write_available = _rb.shm_ring_write_available
+_rb_shm_ring_write_available.argtypes = (ctypes.c_void_p,)
+_rb_shm_ring_write_available.restype = ctypes.c_size_t
+
+_rb_shm_ring_push = _rb.shm_ring_push
+_rb_shm_ring_push.argtypes = (ctypes.c_void_p, ctypes.c_char_p, ctypes.c_size_t, ctypes.POINTER(_Pushed),)
+_rb_shm_ring_push.restype = ctypes.c_int
+
+_rb_shm_ring_read_available = _rb.shm_ring_read_available
+_rb_shm_ring_read_available.argtypes = (ctypes.c_void_p,)
+_rb_shm_ring_read_available.restype = ctypes.c_size_t
+
+_rb_shm_ring_peek = _rb.shm_ring_peek
+_rb_shm_ring_peek.argtypes = (ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t,)
+_rb_shm_ring_peek.restype = ctypes.c_int
+
+_rb_shm_ring_skip = _rb.shm_ring_skip
+_rb_shm_ring_skip.argtypes = (ctypes.c_void_p, ctypes.c_size_t,)
+_rb_shm_ring_skip.restype = ctypes.c_int
+
+_rb_shm_ring_close = _rb.shm_ring_close
+_rb_shm_ring_close.argtypes = (ctypes.c_void_p,)
+_rb_shm_ring_close.restype = ctypes.c_int
+
+# Exception raised for each of the non-zero status codes returned by the library.
+_STATUS_ERRORS = {
+    1: RuntimeError,  # NullPointer
+    2: ValueError,  # OutOfBounds
+    3: MemoryError,  # AllocationFailed
+    4: ValueError,  # InvalidArgument
+    5: BufferFullError,  # Full
+    6: TimeoutError,  # Timeout
+    7: ShutdownError,  # Shutdown
+    8: EvictedError,  # Evicted
+    9: OSError,  # Io
+}
+
+def _check(status):
+    if status != 0:
+        message = _rb_last_error()
+        raise _STATUS_ERRORS.get(status, RuntimeError)(message.decode() if message else f'ringbuf error {status}')
 
 def _check_thread(f):
     @functools.wraps(f)
     def wrapped(self, *args, **kwargs):
-        if threading.get_ident() != self._RingBuffer__tid:
+        if self._RingBuffer__tid is not None and threading.get_ident() != self._RingBuffer__tid:
             raise RuntimeError('RingBuffer is not thread-safe, but was used from a different thread')
 
         return f(self, *args, **kwargs)
@@ -49,70 +516,987 @@ def _check_thread(f):
 
 class RingBuffer:
     """
-    A memory-wise efficient Ring Buffer implementation for working with `bytes`.
+    A memory-wise efficient Ring Buffer implementation for working with `bytes`, or with
+    other fixed-size elements such as 16-bit audio samples.
     """
-    def __init__(self, capacity: int):
+    def __init__(self, capacity: int, mirrored: bool = False, policy: OverflowPolicy = OverflowPolicy.OVERWRITE_OLDEST, thread_safe: bool = False, dtype: str = 'u8', file: str = None):
         """
         Create a new Ring Buffer instance with the given fixed capacity.
 
-        The Ring Buffer will **not** grow when attempting to write past its capacity.
+        The Ring Buffer will **not** grow when attempting to write past its capacity. Instead,
+        the `OverflowPolicy` given by `policy` decides what happens to the data.
+
+        If `mirrored` is set, the buffer memory is mapped twice in a row (where supported) so
+        that peeking never needs to copy data. The capacity is then rounded up to a
+        multiple of the page size. Views into the memory are invalidated by `resize`, which
+        maps new memory, and by any method that changes the buffer.
+
+        If `thread_safe` is set, the buffer can be used from any thread, for example with one
+        thread pushing while another one peeks and skips.
+
+        `dtype` is the type of the elements, one of the keys of `ELEMENT_TYPES`. The capacity
+        and every count are in elements. Buffers of `u8` elements work with `bytes`, while the
+        other ones work with an `array.array` of the matching typecode.
+
+        If `file` is given, the buffer is stored in a memory-mapped file at that path, which
+        keeps its contents across restarts (see `flush`). If the file already exists, its
+        capacity, policy and contents are restored instead of using `capacity` and `policy`.
+        Only non-mirrored buffers of `u8` elements can be stored in a file.
+        """
+        self.__buffer = None
+        self.__thread_safe = False
+        if dtype not in ELEMENT_TYPES:
+            raise ValueError(f'unsupported element type {dtype!r}, expected one of {", ".join(ELEMENT_TYPES)}')
+        self.__dtype = dtype
+        buffer = ctypes.c_void_p()
+        if file is not None:
+            if mirrored or dtype != 'u8':
+                raise ValueError('only non-mirrored buffers of u8 elements can be stored in a file')
+            path = os.fsencode(file)
+            if os.path.exists(path):
+                _check(_rb_open_file(path, ctypes.byref(buffer)))
+            else:
+                _check(_rb_create_file(path, capacity, policy, ctypes.byref(buffer)))
+            self.__buffer = buffer
+        elif mirrored:
+            _check(self.__typed(_rb_new_mirrored)(capacity, ctypes.byref(buffer)))
+            self.__buffer = buffer
+            _check(self.__call(_rb_set_overflow_policy, policy))
+        else:
+            _check(self.__typed(_rb_new_with_policy)(capacity, policy, ctypes.byref(buffer)))
+            self.__buffer = buffer
+        self.__setup(thread_safe)
+
+    def __setup(self, thread_safe):
+        self.__mirrored = self.__call(_rb_is_mirrored)
+
+        if thread_safe:
+            buffer = ctypes.c_void_p()
+            _check(self.__call(_rb_sync_new, ctypes.byref(buffer)))
+            self.__buffer = buffer
+            self.__thread_safe = True
+            self.__tid = None
+        else:
+            self.__tid = threading.get_ident()
+
+    def __typed(self, function):
+        return _FUNCTIONS[self.__dtype][function.__name__]
+
+    def __call(self, function, *args):
+        if self.__thread_safe and function.__name__ in _SYNC_FUNCTIONS[self.__dtype]:
+            function = _SYNC_FUNCTIONS[self.__dtype][function.__name__]
+        else:
+            function = self.__typed(function)
+        return function(self.__buffer, *args)
+
+    @property
+    def thread_safe(self):
+        """
+        Return whether the buffer can be used from any thread.
+        """
+        return self.__thread_safe
+
+    @property
+    def dtype(self):
+        """
+        Return the type of the elements, one of the keys of `ELEMENT_TYPES`.
         """
-        self.__buffer = _rb_new(capacity)
-        self.__tid = threading.get_ident()
+        return self.__dtype
+
+    @property
+    @_check_thread
+    def mirrored(self):
+        """
+        Return whether the buffer memory is mapped twice in a row.
+        """
+        return self.__mirrored
+
+    @property
+    @_check_thread
+    def policy(self):
+        """
+        Return the `OverflowPolicy` used when pushing past the capacity.
+        """
+        return OverflowPolicy(self.__call(_rb_overflow_policy))
+
+    @property
+    @_check_thread
+    def stats(self):
+        """
+        Return the running `Stats` counters of the data that went through the buffer.
+        """
+        stats = _Stats()
+        _check(self.__call(_rb_stats, ctypes.byref(stats)))
+        return Stats(*(getattr(stats, name) for name in Stats._fields))
+
+    @_check_thread
+    def reset_stats(self):
+        """
+        Reset the `stats` counters. The high-water mark starts over from `read_available`.
+        """
+        _check(self.__call(_rb_reset_stats))
+
+    @_check_thread
+    def flush(self):
+        """
+        Wait for the contents of a buffer stored in a file to reach the disk, along with the
+        header describing them. Does nothing for other buffers.
+
+        The file is updated as the buffer changes, so it can be opened again even if the
+        process dies, but only what was flushed is guaranteed to survive a system crash.
+        """
+        _check(self.__call(_rb_flush))
+
+    @_check_thread
+    def serialize(self):
+        """
+        Return the capacity, policy, read position, retention, stats and readable elements of
+        the buffer in a portable, versioned `bytes` format, which `deserialize` restores.
+
+        Consumed elements kept for `rewind`, and the marks, are not included.
+        """
+        while True:
+            n = self.__call(_rb_serialized_len)
+            buffer = ctypes.create_string_buffer(n)
+            written = ctypes.c_size_t()
+            try:
+                _check(self.__call(_rb_serialize, buffer, n, ctypes.byref(written)))
+            except ValueError:
+                # Another thread pushed in the meantime.
+                if not self.__thread_safe:
+                    raise
+                continue
+            return buffer.raw[:written.value]
+
+    @classmethod
+    def deserialize(cls, data: bytes, thread_safe: bool = False, dtype: str = 'u8'):
+        """
+        Restore a buffer from the `bytes` returned by `serialize` for the same `dtype`.
+
+        Buffers which were stored in a file are restored in memory, and mirrored buffers are
+        restored as regular ones if their capacity is not a multiple of the page size here.
+
+        Raise `ValueError` if `data` is not a serialized buffer of this version and `dtype`,
+        if its capacity takes more than 1 GiB, or if its read position and stats do not add up.
+        """
+        buffer = cls.__new__(cls)
+        buffer.__setstate__({'data': data, 'thread_safe': thread_safe, 'dtype': dtype})
+        return buffer
+
+    def __getstate__(self):
+        """
+        Return the state of the buffer for `pickle` and `copy`.
+        """
+        return {'data': self.serialize(), 'thread_safe': self.__thread_safe, 'dtype': self.__dtype}
+
+    def __setstate__(self, state):
+        self.__buffer = None
+        self.__thread_safe = False
+        if state['dtype'] not in ELEMENT_TYPES:
+            raise ValueError(f'unsupported element type {state["dtype"]!r}, expected one of {", ".join(ELEMENT_TYPES)}')
+        self.__dtype = state['dtype']
+        data = state['data']
+        buffer = ctypes.c_void_p()
+        _check(self.__typed(_rb_deserialize)(data, len(data), ctypes.byref(buffer)))
+        self.__buffer = buffer
+        self.__setup(state['thread_safe'])
+
+    @property
+    @_check_thread
+    def capacity(self):
+        """
+        Return the number of elements the buffer can hold.
+
+        Setting it resizes the buffer like `resize`, keeping the newest elements.
+        """
+        return self.__call(_rb_capacity)
+
+    @capacity.setter
+    def capacity(self, capacity):
+        self.resize(capacity)
+
+    @_check_thread
+    def resize(self, capacity, policy: ResizePolicy = ResizePolicy.KEEP_NEWEST):
+        """
+        Change the capacity of the buffer, keeping its contents, and return how many elements
+        were dropped because they didn't fit anymore. The `ResizePolicy` given by `policy`
+        decides which ones.
+
+        The capacity of mirrored buffers is rounded up to a multiple of the page size.
+
+        The contents move to new memory, so views returned by `as_slices` or `reserve` must
+        **not** be used anymore.
+        """
+        dropped = ctypes.c_size_t()
+        _check(self.__call(_rb_resize, capacity, policy, ctypes.byref(dropped)))
+        return dropped.value
 
     @property
     @_check_thread
     def read_available(self):
         """
-        Return the number of bytes which can be read from the queue.
+        Return the number of elements which can be read from the queue.
         """
-        return _rb_read_available(self.__buffer)
+        return self.__call(_rb_read_available)
 
     @property
     @_check_thread
     def write_available(self):
         """
-        Return the number of bytes that can be written into the queue.
+        Return the number of elements that can be written into the queue.
         """
-        return _rb_write_available(self.__buffer)
+        return self.__call(_rb_write_available)
+
+    @property
+    @_check_thread
+    def read_position(self):
+        """
+        Return the position of the oldest readable element, in the stream of every element
+        ever pushed.
+        """
+        return self.__call(_rb_read_position)
+
+    @property
+    @_check_thread
+    def write_position(self):
+        """
+        Return the position the next pushed element will have, in the stream of every element
+        ever pushed.
+        """
+        return self.__call(_rb_write_position)
+
+    def __wait(self, function, n, timeout):
+        if not self.__thread_safe:
+            raise TypeError('only thread-safe buffers can be waited on')
+
+        timeout_ms = -1 if timeout is None else max(0, round(timeout * 1000))
+        available = ctypes.c_size_t()
+        _check(self.__call(function, n, timeout_ms, ctypes.byref(available)))
+        return available.value
+
+    def wait_readable(self, n, timeout=None):
+        """
+        Block until at least `n` elements can be read from a thread-safe buffer, and return
+        `read_available`. The GIL is released while waiting.
+
+        Raise `TimeoutError` if that doesn't happen within `timeout` seconds (or ever, if it's
+        `None`), and `ShutdownError` if `shutdown` is called first.
+        """
+        return self.__wait(_rb_sync_wait_readable, n, timeout)
+
+    def wait_writable(self, n, timeout=None):
+        """
+        Block until at least `n` elements can be written into a thread-safe buffer without
+        overflowing, and return `write_available`. The GIL is released while waiting.
+
+        Raise just like `wait_readable`.
+        """
+        return self.__wait(_rb_sync_wait_writable, n, timeout)
+
+    def shutdown(self):
+        """
+        Wake up every thread waiting on a thread-safe buffer, and make any further waits raise
+        `ShutdownError` instead of blocking.
+        """
+        if not self.__thread_safe:
+            raise TypeError('only thread-safe buffers can be shut down')
+
+        _check(self.__call(_rb_sync_shutdown))
 
     @_check_thread
     def peek(self, n):
         """
-        Peek `n` bytes from the buffer, without removing them from the queue.
+        Peek `n` elements from the buffer, without removing them from the queue.
+
+        Attempting to read more than `read_available` elements will raise `ValueError`.
+        """
+        buffer = (ELEMENT_TYPES[self.__dtype].ctype * n)()
+        if self.__thread_safe:
+            _check(self.__call(_rb_sync_peek, buffer, n))
+        else:
+            copied = ctypes.c_size_t()
+            _check(self.__call(_rb_peek_into, buffer, n, ctypes.byref(copied)))
+            if copied.value < n:
+                raise ValueError(f'cannot access {n} elements, only {copied.value} are available')
+        return self.__elements(buffer, n)
+
+    @_check_thread
+    def as_slices(self, n):
+        """
+        Return the first `n` elements as two read-only `memoryview`s into the buffer memory,
+        without removing them from the queue. The second view is empty unless the elements wrap
+        around the end of the buffer memory. Unlike `peek`, nothing is copied.
+
+        The views must **not** be used after any method that changes the buffer, such as
+        pushing, resizing or deleting it. This is not supported by thread-safe buffers, as
+        other threads could push at any time.
+
+        Attempting to read more than `read_available` elements will raise `ValueError`.
+        """
+        if self.__thread_safe:
+            raise TypeError('thread-safe buffers cannot be viewed')
+
+        slices = _Slices()
+        _check(self.__call(_rb_as_slices, n, ctypes.byref(slices)))
+        return self.__view(slices.first, slices.first_len).toreadonly(), self.__view(slices.second, slices.second_len).toreadonly()
+
+    def __view(self, address, n):
+        element = ELEMENT_TYPES[self.__dtype]
+        view = memoryview((element.ctype * n).from_address(address)).cast('B') if n else memoryview(bytearray())
+        return view.cast(element.typecode)
+
+    def __elements(self, buffer, n):
+        data = bytes(buffer)[:n * ctypes.sizeof(ELEMENT_TYPES[self.__dtype].ctype)]
+        if self.__dtype == 'u8':
+            return data
+        elements = array.array(ELEMENT_TYPES[self.__dtype].typecode)
+        elements.frombytes(data)
+        return elements
+
+    def __array(self, data):
+        elements = array.array(ELEMENT_TYPES[self.__dtype].typecode)
+        try:
+            view = memoryview(data)
+        except TypeError:
+            # A sequence of numbers, converted one by one.
+            elements.extend(data)
+            return elements
+
+        # Anything else holds the raw elements, in native byte order.
+        if view.nbytes % elements.itemsize != 0:
+            raise ValueError(f'{view.nbytes} bytes is not a whole number of {self.__dtype} elements')
+        elements.frombytes(view.cast('B') if view.c_contiguous else view.tobytes())
+        return elements
+
+    def __into(self, function, buffer, n):
+        view = memoryview(buffer).cast('B')
+        fit = len(view) // ctypes.sizeof(ELEMENT_TYPES[self.__dtype].ctype)
+        if n is None:
+            n = fit
+        elif n > fit:
+            raise ValueError(f'cannot copy {n} elements into a buffer of {fit} elements')
+
+        copied = ctypes.c_size_t()
+        dst = (ctypes.c_char * len(view)).from_buffer(view) if n else None
+        _check(self.__call(function, dst, n, ctypes.byref(copied)))
+        return copied.value
+
+    @_check_thread
+    def peek_into(self, buffer, n=None):
+        """
+        Copy up to `n` elements from the start of the queue into the writable `buffer` (such
+        as a `bytearray` or a `memoryview`), without removing them, and return how many were
+        copied.
+
+        By default, as many elements as fit into `buffer` are copied.
+        """
+        return self.__into(_rb_peek_into, buffer, n)
+
+    @_check_thread
+    def peek_at(self, offset, n):
+        """
+        Peek `n` elements starting `offset` elements into the queue, without removing
+        anything.
+
+        Attempting to read past `read_available` elements will raise `ValueError`.
+        """
+        buffer = (ELEMENT_TYPES[self.__dtype].ctype * n)()
+        _check(self.__call(_rb_peek_at, offset, buffer, n))
+        return self.__elements(buffer, n)
+
+    @_check_thread
+    def peek_at_position(self, position, n):
+        """
+        Peek `n` elements starting at the absolute stream `position`, without removing
+        anything.
+
+        Raise `EvictedError` if the position is before `read_position`, and `ValueError` if
+        the elements go past `write_position`.
+        """
+        buffer = (ELEMENT_TYPES[self.__dtype].ctype * n)()
+        _check(self.__call(_rb_peek_at_position, position, buffer, n))
+        return self.__elements(buffer, n)
+
+    @_check_thread
+    def get(self, index):
+        """
+        Return the element `index` elements into the queue, without removing anything.
+
+        Attempting to read past `read_available` elements will raise `ValueError`.
+        """
+        element = ELEMENT_TYPES[self.__dtype].ctype()
+        _check(self.__call(_rb_get, index, ctypes.byref(element)))
+        return element.value
+
+    @_check_thread
+    def read(self, n=None):
+        """
+        Remove and return up to `n` elements from the start of the queue (all of them by
+        default). Return fewer elements, or none at all, if fewer are available.
+        """
+        if n is None:
+            n = self.__call(_rb_read_available)
+        buffer = (ELEMENT_TYPES[self.__dtype].ctype * n)()
+        popped = ctypes.c_size_t()
+        _check(self.__call(_rb_pop, buffer, n, ctypes.byref(popped)))
+        return self.__elements(buffer, popped.value)
+
+    @_check_thread
+    def read_exact(self, n):
+        """
+        Remove and return exactly `n` elements from the start of the queue.
 
-        Attempting to read more than `read_available` bytes will raise an error.
+        Attempting to read more than `read_available` elements will raise `ValueError`, and
+        remove nothing.
         """
-        if n > self.read_available:
-            raise ValueError(f'Cannot read more than {self.read_available}')
+        buffer = (ELEMENT_TYPES[self.__dtype].ctype * n)()
+        _check(self.__call(_rb_pop_exact, buffer, n))
+        return self.__elements(buffer, n)
 
+    @_check_thread
+    def find(self, pattern):
+        """
+        Return the offset of the first occurrence of `pattern` (which is converted just like
+        the data given to `push`) in the queue, or -1 if there is none.
+
+        The search works across the end of the buffer memory without moving anything around.
+        An empty `pattern` raises `ValueError`.
+        """
+        elements = self.__array(pattern)
+        address, m = elements.buffer_info()
+        offset = ctypes.c_size_t()
+        _check(self.__call(_rb_find, address, m, ctypes.byref(offset)))
+        return -1 if offset.value == ctypes.c_size_t(-1).value else offset.value
+
+    @_check_thread
+    def read_until(self, delimiter, limit=None):
+        """
+        Remove and return the elements up to and including the first occurrence of
+        `delimiter`.
+
+        If the delimiter doesn't end within the first `limit` elements, `limit` elements are
+        returned if available, just like `readline` does for files. Otherwise, nothing is
+        removed and an empty result is returned, so incomplete data stays in the queue until
+        the rest of it is pushed. An empty `delimiter` raises `ValueError`.
+        """
+        n = self.__call(_rb_read_available) if limit is None else limit
+        elements = self.__array(delimiter)
+        address, m = elements.buffer_info()
+        buffer = (ELEMENT_TYPES[self.__dtype].ctype * n)()
+        popped = ctypes.c_size_t()
+        _check(self.__call(_rb_pop_until, address, m, buffer, n, ctypes.byref(popped)))
+        if popped.value == 0 and limit is not None and self.__call(_rb_read_available) >= limit:
+            _check(self.__call(_rb_pop_exact, buffer, n))
+            return self.__elements(buffer, n)
+        return self.__elements(buffer, popped.value)
+
+    @_check_thread
+    def readline(self, limit=None):
+        """
+        Remove and return the next line, including its `b'\\n'`, from a buffer of `u8` elements.
+
+        Limits and incomplete lines are handled just like in `read_until`.
+        """
+        if self.__dtype != 'u8':
+            raise TypeError('only buffers of u8 elements have lines')
+        return self.read_until(b'\n', limit)
+
+    @_check_thread
+    def readinto(self, buffer, n=None):
+        """
+        Remove up to `n` elements from the start of the queue, copying them into the writable
+        `buffer` just like `peek_into`, and return how many were removed.
+        """
+        return self.__into(_rb_pop, buffer, n)
+
+    @_check_thread
+    def skip(self, n):
+        """
+        Skip `n` elements from the buffer.
+
+        Attempting to skip more than `read_available` elements will raise `ValueError`.
+        """
+        _check(self.__call(_rb_skip, n))
+
+    @property
+    @_check_thread
+    def retention(self):
+        """
+        Return how many consumed elements are kept track of for `rewind`, 0 by default.
+
+        Consumed elements stay in memory until pushes overwrite them. Setting the retention
+        only affects the elements consumed from then on, unless it shrinks.
+        """
+        return self.__call(_rb_retention)
+
+    @retention.setter
+    @_check_thread
+    def retention(self, retention):
+        _check(self.__call(_rb_set_retention, retention))
+
+    @property
+    @_check_thread
+    def retained(self):
+        """
+        Return how many consumed elements can currently be rewound to.
+        """
+        return self.__call(_rb_retained)
+
+    @_check_thread
+    def rewind(self, n):
+        """
+        Make the last `n` consumed elements readable again.
+
+        Raise `EvictedError` if they are beyond the `retention` or were overwritten already.
+        """
+        _check(self.__call(_rb_rewind_read, n))
+
+    @_check_thread
+    def mark(self, name: str):
+        """
+        Save the current `read_position` under `name`, replacing any previous mark with the
+        same name.
+        """
+        _check(self.__call(_rb_mark, name.encode()))
+
+    @_check_thread
+    def remove_mark(self, name: str):
+        """
+        Forget the mark saved under `name`, and return its position.
+
+        Raise `ValueError` if there is no such mark.
+        """
+        position = ctypes.c_uint64()
+        _check(self.__call(_rb_remove_mark, name.encode(), ctypes.byref(position)))
+        return position.value
+
+    @_check_thread
+    def seek_to_mark(self, name: str):
+        """
+        Move the `read_position` to the mark saved under `name`, rewinding or skipping as
+        needed.
+
+        Raise `ValueError` if there is no such mark or skipping that far is not possible, and
+        `EvictedError` if rewinding that far is not possible.
+        """
+        _check(self.__call(_rb_seek_to_mark, name.encode()))
+
+    @_check_thread
+    def reserve(self, n):
+        """
+        Make room for `n` more elements at the end of the queue, and return the memory to
+        write them into as two writable `memoryview`s, to be filled and then published with
+        `commit`. The second view is empty unless the memory wraps around.
+
+        What happens when the elements don't fit is the same as for `push`, except that with
+        `OverflowPolicy.TRUNCATE_INCOMING` and `OverflowPolicy.DROP_INCOMING` the views are
+        shorter than `n`.
+
+        The views must **not** be used after calling any other method than `commit`. This is
+        not supported by thread-safe buffers.
+        """
+        if self.__thread_safe:
+            raise TypeError('thread-safe buffers cannot be viewed')
+
+        slices = _Slices()
+        _check(self.__call(_rb_reserve, n, ctypes.byref(slices)))
+        return self.__view(slices.first, slices.first_len), self.__view(slices.second, slices.second_len)
+
+    @_check_thread
+    def commit(self, n):
+        """
+        Publish the first `n` elements written into the views returned by `reserve`.
+
+        Attempting to commit more than `write_available` elements will raise `ValueError`.
+        """
+        if self.__thread_safe:
+            raise TypeError('thread-safe buffers cannot be viewed')
+
+        _check(self.__call(_rb_commit, n))
+
+    @_check_thread
+    def push(self, data):
+        """
+        Push the given data elements to the end of the buffer. `data` can be any object
+        holding the raw elements in native byte order, such as `bytes` or an `array.array` of
+        the matching typecode, or any other iterable of numbers, such as a list.
+
+        Raise `ValueError` if the raw data is not a whole number of elements.
+
+        Attempting to push more than `write_available` will act according to the `policy`.
+        Return a `PushResult` with how many of the elements were stored, and how many elements
+        were dropped (either old ones that got overwritten or new ones that did not fit).
+        """
+        elements = self.__array(data)
+        address, n = elements.buffer_info()
+        pushed = _Pushed()
+        _check(self.__call(_rb_push, address, n, ctypes.byref(pushed)))
+        del elements
+        return PushResult(pushed.stored, pushed.dropped)
+
+    def __del__(self):
+        if self.__buffer is not None:
+            _check(self.__call(_rb_del))
+
+def spsc(capacity: int, policy: OverflowPolicy = OverflowPolicy.REJECT):
+    """
+    Create a wait-free ring buffer with the given fixed capacity, for exactly one thread
+    pushing and one thread peeking and skipping.
+
+    Return a `(Producer, Consumer)` pair. Each of them may be used from a different thread,
+    but only from one thread at a time. None of their methods ever block.
+    """
+    producer = ctypes.c_void_p()
+    consumer = ctypes.c_void_p()
+    _check(_rb_spsc_new(capacity, policy, ctypes.byref(producer), ctypes.byref(consumer)))
+    return Producer(producer), Consumer(consumer)
+
+class Producer:
+    """
+    The end of a wait-free ring buffer created by `spsc` which pushes data.
+    """
+    def __init__(self, handle):
+        self.__handle = handle
+
+    @property
+    def write_available(self):
+        """
+        Return the number of bytes that can be pushed without overflowing.
+        """
+        return _rb_spsc_write_available(self.__handle)
+
+    def push(self, data: bytes):
+        """
+        Push the given data bytes to the end of the buffer, just like `RingBuffer.push`.
+        """
+        pushed = _Pushed()
+        _check(_rb_spsc_push(self.__handle, data, len(data), ctypes.byref(pushed)))
+        return PushResult(pushed.stored, pushed.dropped)
+
+    def __del__(self):
+        _check(_rb_spsc_producer_del(self.__handle))
+
+class Consumer:
+    """
+    The end of a wait-free ring buffer created by `spsc` which peeks and skips data.
+    """
+    def __init__(self, handle):
+        self.__handle = handle
+
+    @property
+    def read_available(self):
+        """
+        Return the number of bytes which can be read from the queue.
+        """
+        return _rb_spsc_read_available(self.__handle)
+
+    def peek(self, n):
+        """
+        Peek `n` bytes from the buffer, without removing them from the queue.
+
+        Attempting to read more than `read_available` bytes will raise `ValueError`. If the
+        producer overwrote bytes which were not consumed yet since the last `peek` or `skip`,
+        `EvictedError` is raised and reading resumes from the oldest byte left.
+        """
         buffer = ctypes.create_string_buffer(n)
-        ptr = _rb_peek(self.__buffer, n)
-        ctypes.memmove(buffer, ptr, n)
+        _check(_rb_spsc_peek(self.__handle, buffer, n))
         return buffer.raw
 
-    @_check_thread
     def skip(self, n):
         """
         Skip `n` bytes from the buffer.
 
-        Attempting to skip more than `read_available` bytes will raise an error.
+        Raise just like `peek`, without skipping anything.
         """
-        if n > self.read_available:
-            raise ValueError(f'Cannot skip more than {self.read_available}')
+        _check(_rb_spsc_skip(self.__handle, n))
 
-        _rb_skip(self.__buffer, n)
+    def __del__(self):
+        _check(_rb_spsc_consumer_del(self.__handle))
+
+class BroadcastWriter:
+    """
+    The writer of a ring buffer read by any number of `BroadcastReader`s, each at its own pace.
+    """
+    def __init__(self, capacity: int, policy: OverflowPolicy = OverflowPolicy.OVERWRITE_OLDEST):
+        """
+        Create a new buffer with the given fixed capacity, and no readers yet.
+
+        With `OverflowPolicy.OVERWRITE_OLDEST`, readers lagging more than `capacity` bytes
+        behind are overrun. With the other policies, the slowest reader holds the writer back.
+        """
+        self.__handle = None
+        handle = ctypes.c_void_p()
+        _check(_rb_broadcast_new(capacity, policy, ctypes.byref(handle)))
+        self.__handle = handle
+
+    def reader(self):
+        """
+        Create a `BroadcastReader`, which starts reading at the next pushed byte.
+        """
+        handle = ctypes.c_void_p()
+        _check(_rb_broadcast_reader_new(self.__handle, ctypes.byref(handle)))
+        return BroadcastReader(handle)
+
+    @property
+    def write_available(self):
+        """
+        Return the number of bytes that can be pushed before the slowest reader is overrun or
+        holds the writer back.
+        """
+        return _rb_broadcast_write_available(self.__handle)
 
-    @_check_thread
     def push(self, data: bytes):
         """
-        Push the given data bytes to the end of the buffer.
+        Push the given data bytes to the end of the buffer, just like `RingBuffer.push`.
+
+        The dropped count includes the bytes overwritten before the slowest reader read them.
+        """
+        pushed = _Pushed()
+        _check(_rb_broadcast_push(self.__handle, data, len(data), ctypes.byref(pushed)))
+        return PushResult(pushed.stored, pushed.dropped)
+
+    def __del__(self):
+        if self.__handle is not None:
+            _check(_rb_broadcast_writer_del(self.__handle))
+
+class BroadcastReader:
+    """
+    A reader of a `BroadcastWriter`, which peeks and skips data independently of the other
+    readers. It stops holding the writer back once deleted.
+    """
+    def __init__(self, handle):
+        self.__handle = handle
+
+    @property
+    def read_available(self):
+        """
+        Return the number of bytes which can be read.
+
+        Raise `EvictedError` if the reader was overrun.
+        """
+        n = ctypes.c_size_t()
+        _check(_rb_broadcast_read_available(self.__handle, ctypes.byref(n)))
+        return n.value
+
+    @property
+    def overrun(self):
+        """
+        Return whether the reader was overrun by the writer, and must `resync`.
+        """
+        try:
+            self.read_available
+        except EvictedError:
+            return True
+        return False
+
+    def peek(self, n):
+        """
+        Peek `n` bytes from the buffer, without consuming them.
+
+        Raise `EvictedError` if the reader was overrun, or `ValueError` when attempting to
+        read more than `read_available` bytes.
+        """
+        buffer = ctypes.create_string_buffer(n)
+        _check(_rb_broadcast_peek(self.__handle, buffer, n))
+        return buffer.raw
+
+    def skip(self, n):
+        """
+        Skip `n` bytes from the buffer, for this reader only.
+
+        Raise just like `peek`.
+        """
+        _check(_rb_broadcast_skip(self.__handle, n))
+
+    def read(self, n=None):
+        """
+        Read and consume `n` bytes, or all of the available bytes by default.
+
+        Raise just like `peek`.
+        """
+        if n is None:
+            n = self.read_available
+        data = self.peek(n)
+        self.skip(n)
+        return data
+
+    def resync(self):
+        """
+        Move an overrun reader to the oldest data still in the buffer, and return how many
+        bytes it missed.
+        """
+        missed = ctypes.c_uint64()
+        _check(_rb_broadcast_resync(self.__handle, ctypes.byref(missed)))
+        return missed.value
+
+    def __del__(self):
+        _check(_rb_broadcast_reader_del(self.__handle))
+
+class SharedRing:
+    """
+    A ring buffer in named shared memory, for exactly one process pushing and one process
+    peeking and skipping. None of its methods ever block.
+    """
+    def __init__(self, handle):
+        self.__handle = handle
+
+    @classmethod
+    def create(cls, name: str, capacity: int, policy: OverflowPolicy = OverflowPolicy.REJECT):
+        """
+        Create a new shared memory segment called `name` holding a buffer with the given fixed
+        capacity.
+
+        Raise `OSError` if a segment with the same name already exists, or `ValueError` if
+        `name` contains a slash.
+        """
+        handle = ctypes.c_void_p()
+        _check(_rb_shm_ring_create(name.encode(), capacity, policy, ctypes.byref(handle)))
+        return cls(handle)
+
+    @classmethod
+    def open(cls, name: str):
+        """
+        Attach to the existing shared memory segment called `name`.
+
+        Raise `OSError` if there is no such segment, or `ValueError` if it is not a ring
+        buffer of this version, or is still being created.
+        """
+        handle = ctypes.c_void_p()
+        _check(_rb_shm_ring_open(name.encode(), ctypes.byref(handle)))
+        return cls(handle)
+
+    @staticmethod
+    def unlink(name: str):
+        """
+        Remove the shared memory segment called `name`. Processes attached to it keep using it
+        until they close it.
+        """
+        _check(_rb_shm_ring_unlink(name.encode()))
+
+    @property
+    def capacity(self):
+        return _rb_shm_ring_capacity(self.__handle)
+
+    @property
+    def stats(self):
+        """
+        Return the running `Stats` counters, shared by both processes.
+        """
+        stats = _Stats()
+        _check(_rb_shm_ring_stats(self.__handle, ctypes.byref(stats)))
+        return Stats(*(getattr(stats, name) for name in Stats._fields))
+
+    @property
+    def write_available(self):
+        """
+        Return the number of bytes that can be pushed without overflowing.
+        """
+        return _rb_shm_ring_write_available(self.__handle)
+
+    def push(self, data: bytes):
+        """
+        Push the given data bytes to the end of the buffer, just like `RingBuffer.push`.
+        """
+        pushed = _Pushed()
+        _check(_rb_shm_ring_push(self.__handle, data, len(data), ctypes.byref(pushed)))
+        return PushResult(pushed.stored, pushed.dropped)
+
+    @property
+    def read_available(self):
+        """
+        Return the number of bytes which can be read from the queue.
+        """
+        return _rb_shm_ring_read_available(self.__handle)
+
+    def peek(self, n):
+        """
+        Peek `n` bytes from the buffer, without removing them from the queue.
+
+        Attempting to read more than `read_available` bytes will raise `ValueError`. If the
+        producer overwrote bytes which were not consumed yet since the last `peek` or `skip`,
+        `EvictedError` is raised and reading resumes from the oldest byte left.
+        """
+        buffer = ctypes.create_string_buffer(n)
+        _check(_rb_shm_ring_peek(self.__handle, buffer, n))
+        return buffer.raw
+
+    def skip(self, n):
+        """
+        Skip `n` bytes from the buffer.
+
+        Raise just like `peek`, without skipping anything.
+        """
+        _check(_rb_shm_ring_skip(self.__handle, n))
+
+    def __del__(self):
+        _check(_rb_shm_ring_close(self.__handle))
+
+class RecordQueue:
+    """
+    A queue of whole `bytes` records, which are never split or partially overwritten.
+    """
+    def __init__(self, capacity: int, policy: OverflowPolicy = OverflowPolicy.OVERWRITE_OLDEST):
+        """
+        Create a new queue with the given fixed capacity in bytes, which includes 4 bytes of
+        overhead per record.
+
+        Records that don't fit act according to `policy`, except that they are never
+        truncated: `OverflowPolicy.TRUNCATE_INCOMING` drops them whole.
+        """
+        self.__handle = None
+        handle = ctypes.c_void_p()
+        _check(_rb_record_new(capacity, policy, ctypes.byref(handle)))
+        self.__handle = handle
+
+    def __len__(self):
+        """
+        Return the number of records in the queue.
+        """
+        return _rb_record_count(self.__handle)
+
+    def push(self, record: bytes):
+        """
+        Push a whole record to the end of the queue, and return a `PushResult` with how many
+        records were stored and dropped.
+
+        Raise `BufferFullError` if the record is larger than the capacity, or if it doesn't
+        fit and the policy is `OverflowPolicy.REJECT`.
+        """
+        pushed = _Pushed()
+        _check(_rb_record_push(self.__handle, record, len(record), ctypes.byref(pushed)))
+        return PushResult(pushed.stored, pushed.dropped)
+
+    def peek(self):
+        """
+        Return the oldest record, without removing it from the queue.
+
+        Raise `ValueError` if the queue is empty.
+        """
+        ptr = ctypes.c_void_p()
+        n = ctypes.c_size_t()
+        _check(_rb_record_peek(self.__handle, ctypes.byref(ptr), ctypes.byref(n)))
+        return ctypes.string_at(ptr, n.value) if n.value else b''
+
+    def skip(self):
+        """
+        Remove the oldest record from the queue, and return its length.
+
+        Raise `ValueError` if the queue is empty.
+        """
+        n = ctypes.c_size_t()
+        _check(_rb_record_skip(self.__handle, ctypes.byref(n)))
+        return n.value
+
+    def pop(self):
+        """
+        Remove and return the oldest record.
 
-        Attempting to push more than `write_available` will overwrite the oldest bytes.
+        Raise `ValueError` if the queue is empty.
         """
-        buffer = ctypes.create_string_buffer(data)
-        _rb_push(self.__buffer, ctypes.POINTER(ctypes.c_uint8)(buffer), len(data))
-        del buffer
+        record = self.peek()
+        self.skip()
+        return record
 
     def __del__(self):
-        _rb_del(self.__buffer)
+        if self.__handle is not None:
+            _check(_rb_record_del(self.__handle))
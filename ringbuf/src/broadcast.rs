@@ -0,0 +1,479 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A ring buffer with one writer and any number of readers, each with its own cursor.
+//!
+//! Like in the single-producer single-consumer ring, positions count every byte ever pushed
+//! instead of wrapping around. The `OverflowPolicy` decides what happens when the slowest
+//! reader is a whole capacity behind: with `OverwriteOldest` the writer carries on and
+//! overruns that reader, while the other policies wait for it to catch up.
+use crate::ffi::{slice, slice_mut, status};
+use crate::{Error, OverflowPolicy, Pushed, Status};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+struct State {
+    data: Box<[u8]>,
+    // Position of the next byte to be written.
+    tail: u64,
+    // Position of the next byte to be read by each reader, by reader id.
+    cursors: HashMap<u64, u64>,
+    next_id: u64,
+    policy: OverflowPolicy,
+}
+
+impl State {
+    fn capacity(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// The position of the oldest byte still in the buffer.
+    fn oldest(&self) -> u64 {
+        self.tail.saturating_sub(self.capacity())
+    }
+
+    /// The position of the oldest byte that some reader has not consumed yet and which was
+    /// not overwritten.
+    fn slowest(&self) -> u64 {
+        let slowest = self.cursors.values().copied().min().unwrap_or(self.tail);
+        slowest.max(self.oldest())
+    }
+
+    fn cursor(&self, id: u64) -> Result<u64, Error> {
+        let cursor = self.cursors[&id];
+        if cursor < self.oldest() {
+            return Err(Error::Evicted {
+                requested: cursor,
+                oldest: self.oldest(),
+            });
+        }
+        Ok(cursor)
+    }
+
+    fn check_available(&self, id: u64, n: usize) -> Result<u64, Error> {
+        let cursor = self.cursor(id)?;
+        let available = (self.tail - cursor) as usize;
+        if n > available {
+            return Err(Error::OutOfBounds {
+                requested: n,
+                available,
+            });
+        }
+        Ok(cursor)
+    }
+
+    fn index(&self, position: u64) -> usize {
+        (position % self.capacity()) as usize
+    }
+}
+
+fn lock(shared: &Mutex<State>) -> MutexGuard<'_, State> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The end of a broadcast ring buffer which pushes data, and creates readers.
+pub struct BroadcastWriter {
+    shared: Arc<Mutex<State>>,
+}
+
+/// A reader of a broadcast ring buffer, which peeks and skips data independently of the
+/// other readers.
+///
+/// Readers can be used from any thread, and stop holding the writer back once dropped.
+pub struct BroadcastReader {
+    shared: Arc<Mutex<State>>,
+    id: u64,
+}
+
+impl BroadcastWriter {
+    /// Creates a broadcast ring buffer of the specified capacity and `OverflowPolicy`, with
+    /// no readers yet.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Result<Self, Error> {
+        let mut data = Vec::new();
+        data.try_reserve_exact(capacity)
+            .map_err(|_| Error::AllocationFailed)?;
+        data.resize(capacity, 0);
+
+        let state = State {
+            data: data.into_boxed_slice(),
+            tail: 0,
+            cursors: HashMap::new(),
+            next_id: 0,
+            policy,
+        };
+        Ok(BroadcastWriter {
+            shared: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Creates a reader, which starts reading at the next pushed byte.
+    pub fn reader(&self) -> BroadcastReader {
+        let mut state = lock(&self.shared);
+        let id = state.next_id;
+        state.next_id += 1;
+        let tail = state.tail;
+        state.cursors.insert(id, tail);
+        BroadcastReader {
+            shared: Arc::clone(&self.shared),
+            id,
+        }
+    }
+
+    /// How many readers are there?
+    pub fn readers(&self) -> usize {
+        lock(&self.shared).cursors.len()
+    }
+
+    /// How many bytes can be pushed before the slowest reader is overrun or holds the writer
+    /// back?
+    pub fn write_available(&self) -> usize {
+        let state = lock(&self.shared);
+        (state.capacity() - (state.tail - state.slowest())) as usize
+    }
+
+    /// Pushes data following the `OverflowPolicy`, just like `RingBuffer::push`, where only
+    /// the slowest reader matters.
+    ///
+    /// The bytes reported as dropped are the new ones that did not fit, and the old ones
+    /// overwritten before the slowest reader consumed them.
+    pub fn push(&self, bytes: &[u8]) -> Result<Pushed, Error> {
+        let mut state = lock(&self.shared);
+        let unread = (state.tail - state.slowest()) as usize;
+        let leeway = state.data.len() - unread;
+
+        let (skipped, stored) = if bytes.len() <= leeway {
+            (0, bytes)
+        } else {
+            match state.policy {
+                OverflowPolicy::OverwriteOldest => {
+                    // Only the last `capacity` bytes could possibly remain in the buffer.
+                    let skipped = bytes.len().saturating_sub(state.data.len());
+                    (skipped, &bytes[skipped..])
+                }
+                OverflowPolicy::Reject => {
+                    return Err(Error::Full {
+                        requested: bytes.len(),
+                        available: leeway,
+                    })
+                }
+                OverflowPolicy::TruncateIncoming => (0, &bytes[..leeway]),
+                OverflowPolicy::DropIncoming => (0, &[][..]),
+            }
+        };
+
+        if !stored.is_empty() {
+            let start = state.index(state.tail + skipped as u64);
+            let first = stored.len().min(state.data.len() - start);
+            state.data[start..start + first].copy_from_slice(&stored[..first]);
+            state.data[..stored.len() - first].copy_from_slice(&stored[first..]);
+        }
+        // With `OverwriteOldest`, the skipped bytes are as good as pushed and overwritten.
+        state.tail += (skipped + stored.len()) as u64;
+
+        let evicted = (unread + stored.len()).saturating_sub(state.data.len());
+        Ok(Pushed {
+            stored: stored.len(),
+            dropped: bytes.len() - stored.len() + evicted,
+        })
+    }
+}
+
+impl BroadcastReader {
+    /// How many bytes can be read?
+    ///
+    /// Fails with `Evicted` if the reader was overrun.
+    pub fn read_available(&self) -> Result<usize, Error> {
+        let state = lock(&self.shared);
+        Ok((state.tail - state.cursor(self.id)?) as usize)
+    }
+
+    /// Was the reader overrun by the writer, so that it must `resync` before reading again?
+    pub fn is_overrun(&self) -> bool {
+        lock(&self.shared).cursor(self.id).is_err()
+    }
+
+    /// Copies the oldest `dst.len()` unread bytes into `dst`, without consuming them.
+    ///
+    /// Fails with `Evicted` if the reader was overrun, or with `OutOfBounds` if one tries to
+    /// read more than available.
+    pub fn peek(&self, dst: &mut [u8]) -> Result<(), Error> {
+        let state = lock(&self.shared);
+        let cursor = state.check_available(self.id, dst.len())?;
+        if !dst.is_empty() {
+            let start = state.index(cursor);
+            let first = dst.len().min(state.data.len() - start);
+            let (left, right) = dst.split_at_mut(first);
+            left.copy_from_slice(&state.data[start..start + first]);
+            right.copy_from_slice(&state.data[..right.len()]);
+        }
+        Ok(())
+    }
+
+    /// Consumes the oldest `n` unread bytes.
+    ///
+    /// Fails just like `peek`.
+    pub fn skip(&self, n: usize) -> Result<(), Error> {
+        let mut state = lock(&self.shared);
+        let cursor = state.check_available(self.id, n)?;
+        state.cursors.insert(self.id, cursor + n as u64);
+        Ok(())
+    }
+
+    /// Moves an overrun reader to the oldest byte still in the buffer, and returns how many
+    /// bytes it missed.
+    pub fn resync(&self) -> u64 {
+        let mut state = lock(&self.shared);
+        let oldest = state.oldest();
+        let cursor = state.cursors.get_mut(&self.id).unwrap();
+        let missed = oldest.saturating_sub(*cursor);
+        *cursor = (*cursor).max(oldest);
+        missed
+    }
+}
+
+impl Drop for BroadcastReader {
+    fn drop(&mut self) {
+        lock(&self.shared).cursors.remove(&self.id);
+    }
+}
+
+/// Creates a broadcast ring buffer of the specified capacity and `OverflowPolicy`, and stores
+/// its writer in `out`.
+///
+/// The writer and each of the readers may be used from different threads at the same time.
+/// None of the `broadcast_` functions ever block for long.
+///
+/// Fails with `InvalidArgument` if `policy` is not one of the `OverflowPolicy` values.
+///
+/// It is undefined behaviour to pass an invalid pointer to `out`.
+#[no_mangle]
+pub extern "C" fn broadcast_new(
+    capacity: usize,
+    policy: u32,
+    out: *mut *mut BroadcastWriter,
+) -> Status {
+    status(|| {
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        let writer = BroadcastWriter::new(capacity, OverflowPolicy::try_from(policy)?)?;
+        *out = Box::into_raw(Box::new(writer));
+        Ok(())
+    })
+}
+
+/// Creates a reader, which starts reading at the next pushed byte, and stores it in `out`.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastWriter`, or to pass an invalid pointer to `out`.
+#[no_mangle]
+pub extern "C" fn broadcast_reader_new(
+    writer: *const BroadcastWriter,
+    out: *mut *mut BroadcastReader,
+) -> Status {
+    status(|| {
+        let writer = unsafe { writer.as_ref() }.ok_or(Error::NullPointer)?;
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        *out = Box::into_raw(Box::new(writer.reader()));
+        Ok(())
+    })
+}
+
+/// How much data can be pushed before the slowest reader is overrun or holds the writer back?
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastWriter`.
+#[no_mangle]
+pub extern "C" fn broadcast_write_available(writer: *const BroadcastWriter) -> usize {
+    let writer = unsafe { &*writer };
+    writer.write_available()
+}
+
+/// Pushes data to the buffer, storing how many bytes were stored and dropped in `out`
+/// (unless it's null).
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastWriter`, to pass an invalid pointer to bytes which is not of the matching
+/// length, or to pass an invalid non-null pointer to `out`.
+#[no_mangle]
+pub extern "C" fn broadcast_push(
+    writer: *const BroadcastWriter,
+    bytes: *const u8,
+    n: usize,
+    out: *mut Pushed,
+) -> Status {
+    status(|| {
+        let writer = unsafe { writer.as_ref() }.ok_or(Error::NullPointer)?;
+        let pushed = writer.push(slice(bytes, n)?)?;
+        if let Some(out) = unsafe { out.as_mut() } {
+            *out = pushed;
+        }
+        Ok(())
+    })
+}
+
+/// Deletes the writer. The readers can still read what was pushed.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastWriter`.
+#[no_mangle]
+pub extern "C" fn broadcast_writer_del(writer: *mut BroadcastWriter) -> Status {
+    status(|| {
+        if writer.is_null() {
+            return Err(Error::NullPointer);
+        }
+        drop(unsafe { Box::from_raw(writer) });
+        Ok(())
+    })
+}
+
+/// Stores how much data the reader can read in `out`.
+///
+/// Fails with `Evicted` if the reader was overrun.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastReader`, or to pass an invalid pointer to `out`.
+#[no_mangle]
+pub extern "C" fn broadcast_read_available(
+    reader: *const BroadcastReader,
+    out: *mut usize,
+) -> Status {
+    status(|| {
+        let reader = unsafe { reader.as_ref() }.ok_or(Error::NullPointer)?;
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        *out = reader.read_available()?;
+        Ok(())
+    })
+}
+
+/// Peeks from the buffer, copying the oldest `n` unread bytes into `dst`.
+///
+/// Fails with `Evicted` if the reader was overrun, or with `OutOfBounds` if one tries to
+/// read more than available.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastReader`, or to pass an invalid pointer to `dst` which is not of the matching
+/// length.
+#[no_mangle]
+pub extern "C" fn broadcast_peek(reader: *const BroadcastReader, dst: *mut u8, n: usize) -> Status {
+    status(|| {
+        let reader = unsafe { reader.as_ref() }.ok_or(Error::NullPointer)?;
+        reader.peek(slice_mut(dst, n)?)
+    })
+}
+
+/// Skips data from the buffer, for this reader only.
+///
+/// Fails just like `broadcast_peek`.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastReader`.
+#[no_mangle]
+pub extern "C" fn broadcast_skip(reader: *const BroadcastReader, n: usize) -> Status {
+    status(|| {
+        let reader = unsafe { reader.as_ref() }.ok_or(Error::NullPointer)?;
+        reader.skip(n)
+    })
+}
+
+/// Moves an overrun reader to the oldest data still in the buffer, and stores how many bytes
+/// it missed in `out` (unless it's null).
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastReader`, or to pass an invalid non-null pointer to `out`.
+#[no_mangle]
+pub extern "C" fn broadcast_resync(reader: *const BroadcastReader, out: *mut u64) -> Status {
+    status(|| {
+        let reader = unsafe { reader.as_ref() }.ok_or(Error::NullPointer)?;
+        let missed = reader.resync();
+        if let Some(out) = unsafe { out.as_mut() } {
+            *out = missed;
+        }
+        Ok(())
+    })
+}
+
+/// Deletes the reader, which stops holding the writer back.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+/// `BroadcastReader`.
+#[no_mangle]
+pub extern "C" fn broadcast_reader_del(reader: *mut BroadcastReader) -> Status {
+    status(|| {
+        if reader.is_null() {
+            return Err(Error::NullPointer);
+        }
+        drop(unsafe { Box::from_raw(reader) });
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(reader: &BroadcastReader) -> Vec<u8> {
+        let mut bytes = vec![0; reader.read_available().unwrap()];
+        reader.peek(&mut bytes).unwrap();
+        reader.skip(bytes.len()).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn check_slowest_reader() {
+        let writer = BroadcastWriter::new(4, OverflowPolicy::Reject).unwrap();
+        writer.push(b"lost").unwrap();
+        let fast = writer.reader();
+        let slow = writer.reader();
+        assert_eq!(writer.readers(), 2);
+
+        writer.push(b"abc").unwrap();
+        assert_eq!(read(&fast), b"abc");
+        assert_eq!(writer.write_available(), 1);
+        assert!(writer.push(b"de").is_err());
+
+        slow.skip(2).unwrap();
+        writer.push(b"de").unwrap();
+        assert_eq!(read(&slow), b"cde");
+        assert_eq!(read(&fast), b"de");
+
+        // Dropped readers don't hold the writer back.
+        writer.push(b"fgh").unwrap();
+        assert_eq!(read(&fast), b"fgh");
+        drop(slow);
+        assert_eq!(writer.write_available(), 4);
+    }
+
+    #[test]
+    fn check_overrun_reader() {
+        let writer = BroadcastWriter::new(4, OverflowPolicy::OverwriteOldest).unwrap();
+        let fast = writer.reader();
+        let slow = writer.reader();
+
+        writer.push(b"abc").unwrap();
+        assert_eq!(read(&fast), b"abc");
+        assert_eq!(
+            writer.push(b"defg"),
+            Ok(Pushed {
+                stored: 4,
+                dropped: 3
+            })
+        );
+        assert_eq!(read(&fast), b"defg");
+
+        assert!(slow.is_overrun());
+        assert_eq!(
+            slow.peek(&mut [0]),
+            Err(Error::Evicted {
+                requested: 0,
+                oldest: 3
+            })
+        );
+        assert_eq!(slow.resync(), 3);
+        assert_eq!(read(&slow), b"defg");
+    }
+}
@@ -0,0 +1,1602 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The C ABI of `RingBuffer` and `SyncRingBuffer`.
+//!
+//! There is one set of functions per element type. The functions for `u8` elements are
+//! exported both without suffix (`push`) and with one (`push_u8`), while those for other
+//! elements always have their type as suffix (`push_i16`). Counts and lengths are always in
+//! elements, not bytes.
+use crate::{Error, Status};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use std::time::Duration;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Runs the body of an exported function, recording the error (if any) for `last_error`.
+pub(crate) fn status<F: FnOnce() -> Result<(), Error>>(f: F) -> Status {
+    match f() {
+        Ok(()) => Status::Ok,
+        Err(error) => {
+            let message = CString::new(error.to_string()).ok();
+            LAST_ERROR.with(|last| *last.borrow_mut() = message);
+            error.status()
+        }
+    }
+}
+
+/// Borrows `n` elements from a pointer passed through the C ABI, which may be null if `n`
+/// is 0.
+pub(crate) fn slice<'a, T>(ptr: *const T, n: usize) -> Result<&'a [T], Error> {
+    if n == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(Error::NullPointer)
+    } else {
+        Ok(unsafe { std::slice::from_raw_parts(ptr, n) })
+    }
+}
+
+/// Mutably borrows `n` elements from a pointer passed through the C ABI, which may be null
+/// if `n` is 0.
+pub(crate) fn slice_mut<'a, T>(ptr: *mut T, n: usize) -> Result<&'a mut [T], Error> {
+    if n == 0 {
+        Ok(&mut [])
+    } else if ptr.is_null() {
+        Err(Error::NullPointer)
+    } else {
+        Ok(unsafe { std::slice::from_raw_parts_mut(ptr, n) })
+    }
+}
+
+/// Borrows a nul-terminated UTF-8 string passed through the C ABI.
+pub(crate) fn string<'a>(ptr: *const c_char) -> Result<&'a str, Error> {
+    if ptr.is_null() {
+        return Err(Error::NullPointer);
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| Error::InvalidArgument)
+}
+
+/// Up to two ranges of elements, as returned by `as_slices`.
+#[repr(C)]
+pub struct Slices<T> {
+    /// The range starting at the oldest element.
+    pub first: *const T,
+    pub first_len: usize,
+    /// The range continuing from the start of the buffer memory, if any.
+    pub second: *const T,
+    pub second_len: usize,
+}
+
+/// Up to two ranges of writable elements, as returned by `reserve`.
+#[repr(C)]
+pub struct SlicesMut<T> {
+    /// The range right after the newest element.
+    pub first: *mut T,
+    pub first_len: usize,
+    /// The range continuing from the start of the buffer memory, if any.
+    pub second: *mut T,
+    pub second_len: usize,
+}
+
+/// Converts a timeout received through the C ABI, where negative values mean no timeout.
+fn timeout(timeout_ms: i64) -> Option<Duration> {
+    u64::try_from(timeout_ms).ok().map(Duration::from_millis)
+}
+
+/// Returns a description of the last error that occurred in the calling thread.
+///
+/// Returns null if no function has failed yet. The message is only valid until the next
+/// failing call made from the same thread.
+#[no_mangle]
+pub extern "C" fn last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+macro_rules! exports {
+    ($module:ident, $t:ty, $suffix:literal) => {
+        pub mod $module {
+            use crate::ffi::{slice, slice_mut, status, string, timeout, Slices, SlicesMut};
+            use crate::{
+                Error, OverflowPolicy, Pushed, ResizePolicy, RingBuffer, Stats, Status,
+                SyncRingBuffer,
+            };
+            use std::convert::TryFrom;
+            use std::os::raw::c_char;
+
+            /// Creates a new ring buffer of the specified capacity, and stores it in `out`.
+            ///
+            /// It is undefined behaviour to pass an invalid pointer to `out`.
+            #[export_name = concat!("new", $suffix)]
+            pub extern "C" fn new(capacity: usize, out: *mut *mut RingBuffer<$t>) -> Status {
+                status(|| {
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = Box::into_raw(Box::new(RingBuffer::new(capacity)?));
+                    Ok(())
+                })
+            }
+
+            /// Creates a new ring buffer of the specified capacity and `OverflowPolicy`, and
+            /// stores it in `out`.
+            ///
+            /// Fails with `InvalidArgument` if `policy` is not one of the `OverflowPolicy` values.
+            ///
+            /// It is undefined behaviour to pass an invalid pointer to `out`.
+            #[export_name = concat!("new_with_policy", $suffix)]
+            pub extern "C" fn new_with_policy(
+                capacity: usize,
+                policy: u32,
+                out: *mut *mut RingBuffer<$t>,
+            ) -> Status {
+                status(|| {
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    let policy = OverflowPolicy::try_from(policy)?;
+                    *out = Box::into_raw(Box::new(RingBuffer::with_policy(capacity, policy)?));
+                    Ok(())
+                })
+            }
+
+            /// Creates a new ring buffer whose memory is mapped twice in a row, and stores it
+            /// in `out`.
+            ///
+            /// Peeking from such a buffer never copies. The pointers it returns are invalidated
+            /// by `resize`, which maps new memory, and by any call that changes the buffer.
+            ///
+            /// The capacity is rounded up for the memory to be a multiple of the page size. On
+            /// systems where the memory cannot be mapped this way, a regular buffer of the same
+            /// capacity is created instead.
+            ///
+            /// It is undefined behaviour to pass an invalid pointer to `out`.
+            #[export_name = concat!("new_mirrored", $suffix)]
+            pub extern "C" fn new_mirrored(
+                capacity: usize,
+                out: *mut *mut RingBuffer<$t>,
+            ) -> Status {
+                status(|| {
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = Box::into_raw(Box::new(RingBuffer::new_mirrored(capacity)?));
+                    Ok(())
+                })
+            }
+
+            /// Is the buffer memory mapped twice in a row?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("is_mirrored", $suffix)]
+            pub extern "C" fn is_mirrored(buffer: *mut RingBuffer<$t>) -> bool {
+                let buffer = unsafe { &mut *buffer };
+                buffer.storage.is_mirrored()
+            }
+
+            /// Which `OverflowPolicy` does the buffer use when pushing past its capacity?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("overflow_policy", $suffix)]
+            pub extern "C" fn overflow_policy(buffer: *mut RingBuffer<$t>) -> OverflowPolicy {
+                let buffer = unsafe { &mut *buffer };
+                buffer.policy
+            }
+
+            /// Changes the `OverflowPolicy` used by the buffer from now on.
+            ///
+            /// Fails with `InvalidArgument` if `policy` is not one of the `OverflowPolicy` values.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("set_overflow_policy", $suffix)]
+            pub extern "C" fn set_overflow_policy(
+                buffer: *mut RingBuffer<$t>,
+                policy: u32,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.set_policy(OverflowPolicy::try_from(policy)?);
+                    Ok(())
+                })
+            }
+
+            /// Stores the running counters of the buffer in `out`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("stats", $suffix)]
+            pub extern "C" fn stats(buffer: *mut RingBuffer<$t>, out: *mut Stats) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = buffer.stats;
+                    Ok(())
+                })
+            }
+
+            /// Resets the running counters of the buffer.
+            ///
+            /// The high-water mark starts over from the current amount of readable data.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("reset_stats", $suffix)]
+            pub extern "C" fn reset_stats(buffer: *mut RingBuffer<$t>) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.reset_stats();
+                    Ok(())
+                })
+            }
+
+            /// Waits for the contents of a buffer created by `create_file` to reach the disk,
+            /// along with the header describing them. Does nothing for other buffers.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("flush", $suffix)]
+            pub extern "C" fn flush(buffer: *mut RingBuffer<$t>) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.flush()
+                })
+            }
+
+            /// How many bytes does `serialize` write?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("serialized_len", $suffix)]
+            pub extern "C" fn serialized_len(buffer: *mut RingBuffer<$t>) -> usize {
+                let buffer = unsafe { &*buffer };
+                buffer.serialized_len()
+            }
+
+            /// Serializes the state of the buffer into the first `serialized_len` bytes of
+            /// `dst`, in the portable format read by `deserialize`, storing how many bytes
+            /// were written in `out` (unless it's null).
+            ///
+            /// Fails with `OutOfBounds` if `n` is less than `serialized_len`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, to pass an invalid pointer to `dst` which is not of the matching
+            /// length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("serialize", $suffix)]
+            pub extern "C" fn serialize(
+                buffer: *mut RingBuffer<$t>,
+                dst: *mut u8,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let bytes = buffer.serialize();
+                    if n < bytes.len() {
+                        return Err(Error::OutOfBounds {
+                            requested: bytes.len(),
+                            available: n,
+                        });
+                    }
+                    slice_mut(dst, bytes.len())?.copy_from_slice(&bytes);
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = bytes.len();
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Restores a buffer from `n` bytes written by `serialize` for the same element
+            /// type, and stores it in `out`.
+            ///
+            /// Fails with `InvalidArgument` if the bytes are not a serialized buffer of this
+            /// version and element type, if its capacity takes more than 1 GiB, or if its read
+            /// position and stats do not add up.
+            ///
+            /// It is undefined behaviour to pass an invalid pointer to `bytes` which is not of
+            /// the matching length, or an invalid pointer to `out`.
+            #[export_name = concat!("deserialize", $suffix)]
+            pub extern "C" fn deserialize(
+                bytes: *const u8,
+                n: usize,
+                out: *mut *mut RingBuffer<$t>,
+            ) -> Status {
+                status(|| {
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = Box::into_raw(Box::new(RingBuffer::deserialize(slice(bytes, n)?)?));
+                    Ok(())
+                })
+            }
+
+            /// How many elements can the buffer hold?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("capacity", $suffix)]
+            pub extern "C" fn capacity(buffer: *mut RingBuffer<$t>) -> usize {
+                let buffer = unsafe { &mut *buffer };
+                buffer.capacity()
+            }
+
+            /// Changes the capacity of the buffer, keeping its contents, and stores how many
+            /// elements were dropped because they didn't fit anymore in `out` (unless it's
+            /// null). The `ResizePolicy` given by `policy` decides which ones.
+            ///
+            /// The contents move to new memory, so any pointer previously returned by `peek`,
+            /// `as_slices` or `reserve` is invalidated.
+            ///
+            /// Fails with `InvalidArgument` if `policy` is not one of the `ResizePolicy` values,
+            /// or with `AllocationFailed` if the new memory cannot be allocated.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("resize", $suffix)]
+            pub extern "C" fn resize(
+                buffer: *mut RingBuffer<$t>,
+                capacity: usize,
+                policy: u32,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let policy = ResizePolicy::try_from(policy)?;
+                    let dropped = buffer.resize(capacity, policy)?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = dropped;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// How much data can be read from the buffer?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("read_available", $suffix)]
+            pub extern "C" fn read_available(buffer: *mut RingBuffer<$t>) -> usize {
+                let buffer = unsafe { &mut *buffer };
+                buffer.len
+            }
+
+            /// How much data can be written into the buffer without overwriting contents?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("write_available", $suffix)]
+            pub extern "C" fn write_available(buffer: *mut RingBuffer<$t>) -> usize {
+                let buffer = unsafe { &mut *buffer };
+                buffer.capacity() - buffer.len
+            }
+
+            /// Peeks from the buffer, storing a pointer to the first `n` elements in `out`.
+            ///
+            /// When the elements wrap around the end of the buffer memory, the pointer is to a
+            /// copy of them.
+            ///
+            /// Fails with `OutOfBounds` if one tries to read more than available in the buffer.
+            ///
+            /// The results should **not** be read from after any call that changes the buffer,
+            /// such as pushing, resizing or deleting it.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("peek", $suffix)]
+            pub extern "C" fn peek(
+                buffer: *mut RingBuffer<$t>,
+                n: usize,
+                out: *mut *const $t,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = buffer.peek(n)?.as_ptr();
+                    Ok(())
+                })
+            }
+
+            /// Stores pointers to the first `n` elements of the buffer in `out`, as up to two
+            /// ranges. The second range is empty unless the elements wrap around the end of the
+            /// buffer memory.
+            ///
+            /// Unlike `peek`, this never copies anything.
+            ///
+            /// Fails with `OutOfBounds` if one tries to read more than available in the buffer.
+            ///
+            /// The results should **not** be read from after any call that changes the buffer,
+            /// such as pushing, resizing or deleting it.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("as_slices", $suffix)]
+            pub extern "C" fn as_slices(
+                buffer: *mut RingBuffer<$t>,
+                n: usize,
+                out: *mut Slices<$t>,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    let (first, second) = buffer.as_slices(n)?;
+                    *out = Slices {
+                        first: first.as_ptr(),
+                        first_len: first.len(),
+                        second: second.as_ptr(),
+                        second_len: second.len(),
+                    };
+                    Ok(())
+                })
+            }
+
+            /// Copies up to `n` elements from the start of the buffer into `dst`, without
+            /// consuming them, and stores how many were copied in `out` (unless it's null).
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, to pass an invalid pointer to `dst` which is not of the matching
+            /// length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("peek_into", $suffix)]
+            pub extern "C" fn peek_into(
+                buffer: *mut RingBuffer<$t>,
+                dst: *mut $t,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let copied = buffer.peek_into(slice_mut(dst, n)?);
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = copied;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Skips data from the buffer.
+            ///
+            /// Fails with `OutOfBounds` if one tries to skip more than available in the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("skip", $suffix)]
+            pub extern "C" fn skip(buffer: *mut RingBuffer<$t>, n: usize) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.skip(n)
+                })
+            }
+
+            /// What is the position of the oldest readable element, in the stream of every
+            /// element ever pushed?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("read_position", $suffix)]
+            pub extern "C" fn read_position(buffer: *mut RingBuffer<$t>) -> u64 {
+                let buffer = unsafe { &mut *buffer };
+                buffer.read_position()
+            }
+
+            /// What position will the next pushed element have, in the stream of every element
+            /// ever pushed?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("write_position", $suffix)]
+            pub extern "C" fn write_position(buffer: *mut RingBuffer<$t>) -> u64 {
+                let buffer = unsafe { &mut *buffer };
+                buffer.write_position()
+            }
+
+            /// Copies `n` elements starting at the absolute `position` into `dst`, without
+            /// consuming anything.
+            ///
+            /// Fails with `Evicted` if the position is not in the buffer anymore, or with
+            /// `OutOfBounds` if the range goes past the last pushed element.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `dst` which is not of the
+            /// matching length.
+            #[export_name = concat!("peek_at_position", $suffix)]
+            pub extern "C" fn peek_at_position(
+                buffer: *mut RingBuffer<$t>,
+                position: u64,
+                dst: *mut $t,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.peek_at_position(position, slice_mut(dst, n)?)
+                })
+            }
+
+            /// Copies `n` elements starting `offset` elements into the buffer into `dst`,
+            /// without consuming anything.
+            ///
+            /// Fails with `OutOfBounds` if the range goes past the data available in the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `dst` which is not of the
+            /// matching length.
+            #[export_name = concat!("peek_at", $suffix)]
+            pub extern "C" fn peek_at(
+                buffer: *mut RingBuffer<$t>,
+                offset: usize,
+                dst: *mut $t,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.peek_at(offset, slice_mut(dst, n)?)
+                })
+            }
+
+            /// Stores the element `index` elements into the buffer in `out`, without consuming
+            /// anything.
+            ///
+            /// Fails with `OutOfBounds` if `index` is not less than the data available in the
+            /// buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("get", $suffix)]
+            pub extern "C" fn get(
+                buffer: *mut RingBuffer<$t>,
+                index: usize,
+                out: *mut $t,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.peek_at(index, std::slice::from_mut(out))
+                })
+            }
+
+            /// Searches the readable data for the `m` elements of `pattern`, and stores the
+            /// offset of the first occurrence in `out`, or `SIZE_MAX` if there is none.
+            ///
+            /// The search works across the end of the buffer memory without moving the contents
+            /// around.
+            ///
+            /// Fails with `InvalidArgument` if `m` is 0.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, to pass an invalid pointer to `pattern` which is not of the
+            /// matching length, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("find", $suffix)]
+            pub extern "C" fn find(
+                buffer: *mut RingBuffer<$t>,
+                pattern: *const $t,
+                m: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = buffer.find(slice(pattern, m)?)?.unwrap_or(usize::MAX);
+                    Ok(())
+                })
+            }
+
+            /// Pops the elements up to and including the first occurrence of the `m` elements of
+            /// `delimiter` into `dst`, and stores how many were popped in `out`.
+            ///
+            /// Nothing is popped, and 0 is stored, unless the delimiter ends within the first `n`
+            /// elements.
+            ///
+            /// Fails with `InvalidArgument` if `m` is 0.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, to pass invalid pointers to `delimiter` or `dst` which are not of
+            /// the matching lengths, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("pop_until", $suffix)]
+            pub extern "C" fn pop_until(
+                buffer: *mut RingBuffer<$t>,
+                delimiter: *const $t,
+                m: usize,
+                dst: *mut $t,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    let delimiter = slice(delimiter, m)?;
+                    *out = buffer
+                        .pop_until(delimiter, slice_mut(dst, n)?)?
+                        .unwrap_or(0);
+                    Ok(())
+                })
+            }
+
+            /// Copies the first `n` elements of the buffer into `dst` and consumes them.
+            ///
+            /// Fails with `OutOfBounds`, without consuming anything, if one tries to pop more
+            /// than available in the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `dst` which is not of the
+            /// matching length.
+            #[export_name = concat!("pop_exact", $suffix)]
+            pub extern "C" fn pop_exact(
+                buffer: *mut RingBuffer<$t>,
+                dst: *mut $t,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.pop_exact(slice_mut(dst, n)?)
+                })
+            }
+
+            /// Copies up to `n` elements from the start of the buffer into `dst`, consumes
+            /// them, and stores how many were popped in `out` (unless it's null).
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, to pass an invalid pointer to `dst` which is not of the
+            /// matching length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("pop", $suffix)]
+            pub extern "C" fn pop(
+                buffer: *mut RingBuffer<$t>,
+                dst: *mut $t,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let popped = buffer.pop(slice_mut(dst, n)?);
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = popped;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// How many consumed elements does the buffer keep track of for `rewind_read`?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("retention", $suffix)]
+            pub extern "C" fn retention(buffer: *mut RingBuffer<$t>) -> usize {
+                let buffer = unsafe { &mut *buffer };
+                buffer.retention()
+            }
+
+            /// Changes how many consumed elements the buffer keeps track of for `rewind_read`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("set_retention", $suffix)]
+            pub extern "C" fn set_retention(
+                buffer: *mut RingBuffer<$t>,
+                retention: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.set_retention(retention);
+                    Ok(())
+                })
+            }
+
+            /// How many consumed elements can currently be rewound to?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("retained", $suffix)]
+            pub extern "C" fn retained(buffer: *mut RingBuffer<$t>) -> usize {
+                let buffer = unsafe { &mut *buffer };
+                buffer.retained()
+            }
+
+            /// Makes the last `n` consumed elements readable again.
+            ///
+            /// Fails with `Evicted` if they are beyond the retention or were overwritten
+            /// already.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("rewind_read", $suffix)]
+            pub extern "C" fn rewind_read(buffer: *mut RingBuffer<$t>, n: usize) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.rewind(n)
+                })
+            }
+
+            /// Saves the current read position under the nul-terminated UTF-8 `name`,
+            /// replacing any previous mark with the same name.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `name`.
+            #[export_name = concat!("mark", $suffix)]
+            pub extern "C" fn mark(buffer: *mut RingBuffer<$t>, name: *const c_char) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.mark(string(name)?);
+                    Ok(())
+                })
+            }
+
+            /// Forgets the mark saved under `name`, and stores its position in `out` (unless
+            /// it's null).
+            ///
+            /// Fails with `InvalidArgument` if there is no such mark.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, to pass an invalid pointer to `name`, or to pass an invalid non-null
+            /// pointer to `out`.
+            #[export_name = concat!("remove_mark", $suffix)]
+            pub extern "C" fn remove_mark(
+                buffer: *mut RingBuffer<$t>,
+                name: *const c_char,
+                out: *mut u64,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let position = buffer
+                        .remove_mark(string(name)?)
+                        .ok_or(Error::InvalidArgument)?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = position;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Moves the read position to the mark saved under `name`, rewinding or skipping
+            /// as needed.
+            ///
+            /// Fails with `InvalidArgument` if there is no such mark, with `Evicted` if
+            /// rewinding that far is not possible, or with `OutOfBounds` if skipping that far
+            /// is not possible.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `name`.
+            #[export_name = concat!("seek_to_mark", $suffix)]
+            pub extern "C" fn seek_to_mark(
+                buffer: *mut RingBuffer<$t>,
+                name: *const c_char,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.seek_to_mark(string(name)?)
+                })
+            }
+
+            /// Pushes data to the buffer, storing how many elements were stored and dropped in
+            /// `out` (unless it's null).
+            ///
+            /// What happens when the data doesn't fit depends on the buffer's `OverflowPolicy`.
+            /// With `Reject`, the push fails with `Full`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, to pass an invalid pointer to data which is not of the matching
+            /// length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("push", $suffix)]
+            pub extern "C" fn push(
+                buffer: *mut RingBuffer<$t>,
+                data: *const $t,
+                n: usize,
+                out: *mut Pushed,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let pushed = buffer.push(slice(data, n)?)?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = pushed;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Makes room for `n` more elements at the end of the buffer, and stores pointers to
+            /// the writable memory in `out` as up to two ranges, to be filled and then published
+            /// with `commit`.
+            ///
+            /// What happens when the elements don't fit is the same as for `push`, except that
+            /// with `TruncateIncoming` and `DropIncoming` the ranges are shorter than `n`.
+            ///
+            /// The ranges should **not** be written to after calling any other function on the
+            /// buffer, other than `commit`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("reserve", $suffix)]
+            pub extern "C" fn reserve(
+                buffer: *mut RingBuffer<$t>,
+                n: usize,
+                out: *mut SlicesMut<$t>,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    let (first, second) = buffer.reserve(n)?;
+                    *out = SlicesMut {
+                        first: first.as_mut_ptr(),
+                        first_len: first.len(),
+                        second: second.as_mut_ptr(),
+                        second_len: second.len(),
+                    };
+                    Ok(())
+                })
+            }
+
+            /// Publishes the first `n` elements written into the ranges returned by `reserve`.
+            ///
+            /// Fails with `OutOfBounds` if there is no room for `n` more elements.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("commit", $suffix)]
+            pub extern "C" fn commit(buffer: *mut RingBuffer<$t>, n: usize) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.commit(n)
+                })
+            }
+
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`.
+            #[export_name = concat!("del", $suffix)]
+            pub extern "C" fn del(buffer: *mut RingBuffer<$t>) -> Status {
+                status(|| {
+                    if buffer.is_null() {
+                        return Err(Error::NullPointer);
+                    }
+                    let buffer = unsafe { Box::from_raw(buffer) };
+                    drop(buffer);
+                    Ok(())
+                })
+            }
+
+            /// Turns a ring buffer created by any of the `new` functions into a thread-safe
+            /// one, and stores it in `out`.
+            ///
+            /// On success, this takes ownership of `buffer`, which must not be used or deleted
+            /// anymore. Only the `sync_` functions can be used on the new buffer, from any
+            /// thread.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `RingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("sync_new", $suffix)]
+            pub extern "C" fn sync_new(
+                buffer: *mut RingBuffer<$t>,
+                out: *mut *mut SyncRingBuffer<$t>,
+            ) -> Status {
+                status(|| {
+                    if buffer.is_null() {
+                        return Err(Error::NullPointer);
+                    }
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    let buffer = unsafe { Box::from_raw(buffer) };
+                    *out = Box::into_raw(Box::new(SyncRingBuffer::new(*buffer)));
+                    Ok(())
+                })
+            }
+
+            /// Which `OverflowPolicy` does the buffer use when pushing past its capacity?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_overflow_policy", $suffix)]
+            pub extern "C" fn sync_overflow_policy(
+                buffer: *const SyncRingBuffer<$t>,
+            ) -> OverflowPolicy {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().policy
+            }
+
+            /// Changes the `OverflowPolicy` used by the buffer from now on.
+            ///
+            /// Fails with `InvalidArgument` if `policy` is not one of the `OverflowPolicy` values.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_set_overflow_policy", $suffix)]
+            pub extern "C" fn sync_set_overflow_policy(
+                buffer: *const SyncRingBuffer<$t>,
+                policy: u32,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().set_policy(OverflowPolicy::try_from(policy)?);
+                    Ok(())
+                })
+            }
+
+            /// Stores the running counters of the buffer in `out`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("sync_stats", $suffix)]
+            pub extern "C" fn sync_stats(
+                buffer: *const SyncRingBuffer<$t>,
+                out: *mut Stats,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = buffer.lock().stats;
+                    Ok(())
+                })
+            }
+
+            /// Resets the running counters of the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_reset_stats", $suffix)]
+            pub extern "C" fn sync_reset_stats(buffer: *const SyncRingBuffer<$t>) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().reset_stats();
+                    Ok(())
+                })
+            }
+
+            /// Waits for the contents of a buffer created by `create_file` to reach the disk,
+            /// along with the header describing them. Does nothing for other buffers.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_flush", $suffix)]
+            pub extern "C" fn sync_flush(buffer: *const SyncRingBuffer<$t>) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().flush()
+                })
+            }
+
+            /// How many bytes does `sync_serialize` write, unless the buffer changes in the
+            /// meantime?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_serialized_len", $suffix)]
+            pub extern "C" fn sync_serialized_len(buffer: *const SyncRingBuffer<$t>) -> usize {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().serialized_len()
+            }
+
+            /// Serializes the state of the buffer into `dst`, storing how many bytes were
+            /// written in `out` (unless it's null), just like `serialize`.
+            ///
+            /// Fails with `OutOfBounds` if `n` is less than the serialized length, which may
+            /// have grown since `sync_serialized_len` was called.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, to pass an invalid pointer to `dst` which is not of the
+            /// matching length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("sync_serialize", $suffix)]
+            pub extern "C" fn sync_serialize(
+                buffer: *const SyncRingBuffer<$t>,
+                dst: *mut u8,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let bytes = buffer.lock().serialize();
+                    if n < bytes.len() {
+                        return Err(Error::OutOfBounds {
+                            requested: bytes.len(),
+                            available: n,
+                        });
+                    }
+                    slice_mut(dst, bytes.len())?.copy_from_slice(&bytes);
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = bytes.len();
+                    }
+                    Ok(())
+                })
+            }
+
+            /// How many elements can the buffer hold?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_capacity", $suffix)]
+            pub extern "C" fn sync_capacity(buffer: *const SyncRingBuffer<$t>) -> usize {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().capacity()
+            }
+
+            /// Changes the capacity of the buffer, keeping its contents, and stores how many
+            /// elements were dropped because they didn't fit anymore in `out` (unless it's
+            /// null). The `ResizePolicy` given by `policy` decides which ones.
+            ///
+            /// Fails with `InvalidArgument` if `policy` is not one of the `ResizePolicy` values,
+            /// or with `AllocationFailed` if the new memory cannot be allocated.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("sync_resize", $suffix)]
+            pub extern "C" fn sync_resize(
+                buffer: *const SyncRingBuffer<$t>,
+                capacity: usize,
+                policy: u32,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let policy = ResizePolicy::try_from(policy)?;
+                    let dropped = buffer.lock().resize(capacity, policy)?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = dropped;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// How much data can be read from the buffer?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_read_available", $suffix)]
+            pub extern "C" fn sync_read_available(buffer: *const SyncRingBuffer<$t>) -> usize {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().len
+            }
+
+            /// How much data can be written into the buffer without overwriting contents?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_write_available", $suffix)]
+            pub extern "C" fn sync_write_available(buffer: *const SyncRingBuffer<$t>) -> usize {
+                let buffer = unsafe { &*buffer };
+                let buffer = buffer.lock();
+                buffer.capacity() - buffer.len
+            }
+
+            /// Peeks from the buffer, copying the first `n` elements into `dst`.
+            ///
+            /// Unlike `peek`, this copies the data while the buffer is locked, so it can't be
+            /// changed by other threads halfway through.
+            ///
+            /// Fails with `OutOfBounds` if one tries to read more than available in the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `dst` which is not of the
+            /// matching length.
+            #[export_name = concat!("sync_peek", $suffix)]
+            pub extern "C" fn sync_peek(
+                buffer: *const SyncRingBuffer<$t>,
+                dst: *mut $t,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().copy_out(slice_mut(dst, n)?)
+                })
+            }
+
+            /// Copies up to `n` elements from the start of the buffer into `dst`, without
+            /// consuming them, and stores how many were copied in `out` (unless it's null).
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, to pass an invalid pointer to `dst` which is not of the
+            /// matching length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("sync_peek_into", $suffix)]
+            pub extern "C" fn sync_peek_into(
+                buffer: *const SyncRingBuffer<$t>,
+                dst: *mut $t,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let copied = buffer.lock().peek_into(slice_mut(dst, n)?);
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = copied;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Skips data from the buffer.
+            ///
+            /// Fails with `OutOfBounds` if one tries to skip more than available in the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_skip", $suffix)]
+            pub extern "C" fn sync_skip(buffer: *const SyncRingBuffer<$t>, n: usize) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().skip(n)
+                })
+            }
+
+            /// What is the position of the oldest readable element, in the stream of every
+            /// element ever pushed?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_read_position", $suffix)]
+            pub extern "C" fn sync_read_position(buffer: *const SyncRingBuffer<$t>) -> u64 {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().read_position()
+            }
+
+            /// What position will the next pushed element have, in the stream of every element
+            /// ever pushed?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_write_position", $suffix)]
+            pub extern "C" fn sync_write_position(buffer: *const SyncRingBuffer<$t>) -> u64 {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().write_position()
+            }
+
+            /// Copies `n` elements starting at the absolute `position` into `dst`, without
+            /// consuming anything.
+            ///
+            /// Fails with `Evicted` if the position is not in the buffer anymore, or with
+            /// `OutOfBounds` if the range goes past the last pushed element.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `dst` which is not of the
+            /// matching length.
+            #[export_name = concat!("sync_peek_at_position", $suffix)]
+            pub extern "C" fn sync_peek_at_position(
+                buffer: *const SyncRingBuffer<$t>,
+                position: u64,
+                dst: *mut $t,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().peek_at_position(position, slice_mut(dst, n)?)
+                })
+            }
+
+            /// Copies `n` elements starting `offset` elements into the buffer into `dst`,
+            /// without consuming anything.
+            ///
+            /// Fails with `OutOfBounds` if the range goes past the data available in the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `dst` which is not of the
+            /// matching length.
+            #[export_name = concat!("sync_peek_at", $suffix)]
+            pub extern "C" fn sync_peek_at(
+                buffer: *const SyncRingBuffer<$t>,
+                offset: usize,
+                dst: *mut $t,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().peek_at(offset, slice_mut(dst, n)?)
+                })
+            }
+
+            /// Stores the element `index` elements into the buffer in `out`, without consuming
+            /// anything.
+            ///
+            /// Fails with `OutOfBounds` if `index` is not less than the data available in the
+            /// buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("sync_get", $suffix)]
+            pub extern "C" fn sync_get(
+                buffer: *const SyncRingBuffer<$t>,
+                index: usize,
+                out: *mut $t,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().peek_at(index, std::slice::from_mut(out))
+                })
+            }
+
+            /// Searches the readable data for the `m` elements of `pattern`, and stores the
+            /// offset of the first occurrence in `out`, or `SIZE_MAX` if there is none.
+            ///
+            /// The search works across the end of the buffer memory without moving the contents
+            /// around.
+            ///
+            /// Fails with `InvalidArgument` if `m` is 0.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, to pass an invalid pointer to `pattern` which is not of the
+            /// matching length, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("sync_find", $suffix)]
+            pub extern "C" fn sync_find(
+                buffer: *const SyncRingBuffer<$t>,
+                pattern: *const $t,
+                m: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    *out = buffer
+                        .lock()
+                        .find(slice(pattern, m)?)?
+                        .unwrap_or(usize::MAX);
+                    Ok(())
+                })
+            }
+
+            /// Pops the elements up to and including the first occurrence of the `m` elements of
+            /// `delimiter` into `dst`, and stores how many were popped in `out`.
+            ///
+            /// Nothing is popped, and 0 is stored, unless the delimiter ends within the first `n`
+            /// elements.
+            ///
+            /// Fails with `InvalidArgument` if `m` is 0.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, to pass invalid pointers to `delimiter` or `dst` which are not of
+            /// the matching lengths, or to pass an invalid pointer to `out`.
+            #[export_name = concat!("sync_pop_until", $suffix)]
+            pub extern "C" fn sync_pop_until(
+                buffer: *const SyncRingBuffer<$t>,
+                delimiter: *const $t,
+                m: usize,
+                dst: *mut $t,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+                    let delimiter = slice(delimiter, m)?;
+                    *out = buffer
+                        .lock()
+                        .pop_until(delimiter, slice_mut(dst, n)?)?
+                        .unwrap_or(0);
+                    Ok(())
+                })
+            }
+
+            /// Copies the first `n` elements of the buffer into `dst` and consumes them.
+            ///
+            /// Fails with `OutOfBounds`, without consuming anything, if one tries to pop more
+            /// than available in the buffer.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `dst` which is not of the
+            /// matching length.
+            #[export_name = concat!("sync_pop_exact", $suffix)]
+            pub extern "C" fn sync_pop_exact(
+                buffer: *const SyncRingBuffer<$t>,
+                dst: *mut $t,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().pop_exact(slice_mut(dst, n)?)
+                })
+            }
+
+            /// Copies up to `n` elements from the start of the buffer into `dst`, consumes
+            /// them, and stores how many were popped in `out` (unless it's null).
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, to pass an invalid pointer to `dst` which is not of the
+            /// matching length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("sync_pop", $suffix)]
+            pub extern "C" fn sync_pop(
+                buffer: *const SyncRingBuffer<$t>,
+                dst: *mut $t,
+                n: usize,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let popped = buffer.lock().pop(slice_mut(dst, n)?);
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = popped;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// How many consumed elements does the buffer keep track of for `sync_rewind_read`?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_retention", $suffix)]
+            pub extern "C" fn sync_retention(buffer: *const SyncRingBuffer<$t>) -> usize {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().retention()
+            }
+
+            /// Changes how many consumed elements the buffer keeps track of for `sync_rewind_read`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_set_retention", $suffix)]
+            pub extern "C" fn sync_set_retention(
+                buffer: *const SyncRingBuffer<$t>,
+                retention: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().set_retention(retention);
+                    Ok(())
+                })
+            }
+
+            /// How many consumed elements can currently be rewound to?
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_retained", $suffix)]
+            pub extern "C" fn sync_retained(buffer: *const SyncRingBuffer<$t>) -> usize {
+                let buffer = unsafe { &*buffer };
+                buffer.lock().retained()
+            }
+
+            /// Makes the last `n` consumed elements readable again.
+            ///
+            /// Fails with `Evicted` if they are beyond the retention or were overwritten
+            /// already.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_rewind_read", $suffix)]
+            pub extern "C" fn sync_rewind_read(
+                buffer: *const SyncRingBuffer<$t>,
+                n: usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().rewind(n)
+                })
+            }
+
+            /// Saves the current read position under the nul-terminated UTF-8 `name`,
+            /// replacing any previous mark with the same name.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `name`.
+            #[export_name = concat!("sync_mark", $suffix)]
+            pub extern "C" fn sync_mark(
+                buffer: *const SyncRingBuffer<$t>,
+                name: *const c_char,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().mark(string(name)?);
+                    Ok(())
+                })
+            }
+
+            /// Forgets the mark saved under `name`, and stores its position in `out` (unless
+            /// it's null).
+            ///
+            /// Fails with `InvalidArgument` if there is no such mark.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, to pass an invalid pointer to `name`, or to pass an invalid non-null
+            /// pointer to `out`.
+            #[export_name = concat!("sync_remove_mark", $suffix)]
+            pub extern "C" fn sync_remove_mark(
+                buffer: *const SyncRingBuffer<$t>,
+                name: *const c_char,
+                out: *mut u64,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let position = buffer
+                        .lock()
+                        .remove_mark(string(name)?)
+                        .ok_or(Error::InvalidArgument)?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = position;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Moves the read position to the mark saved under `name`, rewinding or skipping
+            /// as needed.
+            ///
+            /// Fails with `InvalidArgument` if there is no such mark, with `Evicted` if
+            /// rewinding that far is not possible, or with `OutOfBounds` if skipping that far
+            /// is not possible.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid pointer to `name`.
+            #[export_name = concat!("sync_seek_to_mark", $suffix)]
+            pub extern "C" fn sync_seek_to_mark(
+                buffer: *const SyncRingBuffer<$t>,
+                name: *const c_char,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.lock().seek_to_mark(string(name)?)
+                })
+            }
+
+            /// Pushes data to the buffer, storing how many elements were stored and dropped in
+            /// `out` (unless it's null).
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, to pass an invalid pointer to data which is not of the
+            /// matching length, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("sync_push", $suffix)]
+            pub extern "C" fn sync_push(
+                buffer: *const SyncRingBuffer<$t>,
+                data: *const $t,
+                n: usize,
+                out: *mut Pushed,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let pushed = buffer.lock().push(slice(data, n)?)?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = pushed;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Blocks until at least `n` elements can be read, and stores how many can be read
+            /// in `out` (unless it's null).
+            ///
+            /// Fails with `Timeout` if that doesn't happen within `timeout_ms` milliseconds (a
+            /// negative timeout waits forever), with `Shutdown` if `sync_shutdown` is called
+            /// first, or with `OutOfBounds` if `n` is larger than the capacity.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("sync_wait_readable", $suffix)]
+            pub extern "C" fn sync_wait_readable(
+                buffer: *const SyncRingBuffer<$t>,
+                n: usize,
+                timeout_ms: i64,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let available = buffer.wait_readable(n, timeout(timeout_ms))?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = available;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Blocks until at least `n` elements can be written without overflowing, and
+            /// stores how many can be written in `out` (unless it's null).
+            ///
+            /// Fails just like `sync_wait_readable`.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to pass an invalid non-null pointer to `out`.
+            #[export_name = concat!("sync_wait_writable", $suffix)]
+            pub extern "C" fn sync_wait_writable(
+                buffer: *const SyncRingBuffer<$t>,
+                n: usize,
+                timeout_ms: i64,
+                out: *mut usize,
+            ) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    let available = buffer.wait_writable(n, timeout(timeout_ms))?;
+                    if let Some(out) = unsafe { out.as_mut() } {
+                        *out = available;
+                    }
+                    Ok(())
+                })
+            }
+
+            /// Wakes up every thread waiting on the buffer, and makes any further waits fail
+            /// with `Shutdown` instead of blocking.
+            ///
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`.
+            #[export_name = concat!("sync_shutdown", $suffix)]
+            pub extern "C" fn sync_shutdown(buffer: *const SyncRingBuffer<$t>) -> Status {
+                status(|| {
+                    let buffer = unsafe { buffer.as_ref() }.ok_or(Error::NullPointer)?;
+                    buffer.shutdown();
+                    Ok(())
+                })
+            }
+
+            /// It is undefined behaviour to pass a pointer not pointing to a non-deleted
+            /// `SyncRingBuffer`, or to use it from other threads while deleting it.
+            #[export_name = concat!("sync_del", $suffix)]
+            pub extern "C" fn sync_del(buffer: *mut SyncRingBuffer<$t>) -> Status {
+                status(|| {
+                    if buffer.is_null() {
+                        return Err(Error::NullPointer);
+                    }
+                    let buffer = unsafe { Box::from_raw(buffer) };
+                    drop(buffer);
+                    Ok(())
+                })
+            }
+        }
+    };
+}
+
+exports!(default, u8, "");
+exports!(uint8, u8, "_u8");
+exports!(int16, i16, "_i16");
+exports!(int32, i32, "_i32");
+exports!(float32, f32, "_f32");
+exports!(float64, f64, "_f64");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn check_out_of_bounds_status() {
+        use default::*;
+
+        let mut buffer = ptr::null_mut();
+        assert_eq!(new(4, &mut buffer), Status::Ok);
+        assert_eq!(
+            push(buffer, [1, 2].as_ptr(), 2, ptr::null_mut()),
+            Status::Ok
+        );
+
+        let mut out = ptr::null();
+        assert_eq!(peek(buffer, 3, &mut out), Status::OutOfBounds);
+        assert_eq!(skip(buffer, 3), Status::OutOfBounds);
+        let message = unsafe { CStr::from_ptr(last_error()) };
+        assert_eq!(
+            message.to_str().unwrap(),
+            "cannot access 3 elements, only 2 are available"
+        );
+
+        // An empty delimiter is refused instead of looking like one that was not found.
+        let (mut dst, mut popped) = ([0; 2], 1);
+        let delimiter: [u8; 0] = [];
+        assert_eq!(
+            pop_until(
+                buffer,
+                delimiter.as_ptr(),
+                0,
+                dst.as_mut_ptr(),
+                2,
+                &mut popped
+            ),
+            Status::InvalidArgument
+        );
+
+        // Failed calls leave the buffer untouched.
+        assert_eq!(read_available(buffer), 2);
+        assert_eq!(del(buffer), Status::Ok);
+        assert_eq!(del(ptr::null_mut()), Status::NullPointer);
+    }
+
+    #[test]
+    fn check_typed_exports() {
+        let mut buffer = ptr::null_mut();
+        assert_eq!(int16::new(4, &mut buffer), Status::Ok);
+        assert_eq!(
+            int16::push(buffer, [-1, 2, -3].as_ptr(), 3, ptr::null_mut()),
+            Status::Ok
+        );
+        assert_eq!(int16::read_available(buffer), 3);
+        assert_eq!(int16::write_available(buffer), 1);
+
+        let mut out = ptr::null();
+        assert_eq!(int16::peek(buffer, 2, &mut out), Status::Ok);
+        assert_eq!(unsafe { std::slice::from_raw_parts(out, 2) }, &[-1, 2]);
+        assert_eq!(int16::del(buffer), Status::Ok);
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Byte buffers stored in a memory-mapped file, which keep their contents across restarts.
+//!
+//! The file starts with a header of `HEADER_LEN` bytes, all integers little-endian:
+//!
+//! | Offset | Field                                    |
+//! |--------|------------------------------------------|
+//! | 0      | magic, `b"RINGFILE"`                     |
+//! | 8      | version, `u32`                           |
+//! | 12     | reserved, `u32`                          |
+//! | 16     | capacity, `u64`                          |
+//! | 24     | reserved, `u64`                          |
+//! | 32     | first slot holding the state             |
+//! | 80     | second slot holding the state            |
+//!
+//! The rest of the header is reserved, and the bytes follow it. Each slot holds:
+//!
+//! | Offset | Field                                                          |
+//! |--------|----------------------------------------------------------------|
+//! | 0      | sequence number, `u64`                                         |
+//! | 8      | `OverflowPolicy`, `u32`                                        |
+//! | 12     | reserved, `u32`                                                |
+//! | 16     | head, the offset of the oldest readable byte, `u64`            |
+//! | 24     | number of readable bytes, `u64`                                |
+//! | 32     | read position, `u64`                                           |
+//! | 40     | FNV-1a checksum of the first 32 bytes of the file and the slot |
+//!
+//! The state is saved after every change, alternately in either slot with the next sequence
+//! number, checksum last. The file is reopened from the valid slot with the highest sequence
+//! number, so a crash in the middle of an update only loses that update.
+use crate::ffi::status;
+use crate::storage::{Mapping, Storage};
+use crate::{Error, OverflowPolicy, RingBuffer, Status};
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::fs::OpenOptions;
+use std::os::raw::c_char;
+use std::path::Path;
+
+/// Length of the header at the start of the file.
+pub(crate) const HEADER_LEN: usize = 128;
+
+const MAGIC: &[u8; 8] = b"RINGFILE";
+
+/// Version of the header layout, bumped on any incompatible change.
+const VERSION: u32 = 1;
+
+/// Length of the fields which never change, covered by the checksum of both slots.
+const FIXED_LEN: usize = 32;
+
+/// Offsets of the slots, the one used for a sequence number being `SLOTS[sequence % 2]`.
+const SLOTS: [usize; 2] = [32, 80];
+
+/// Offset of the checksum in a slot, which covers the fields before it.
+const CHECKSUM: usize = 40;
+
+/// The state of a buffer which is saved in the header of its file.
+struct Header {
+    policy: OverflowPolicy,
+    capacity: u64,
+    head: u64,
+    len: u64,
+    position: u64,
+}
+
+/// 64-bit FNV-1a hash of the concatenated `parts`.
+fn checksum(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3)
+        })
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut field = [0; 8];
+    field.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(field)
+}
+
+impl Header {
+    /// Writes the header into the slot of `sequence`, leaving the other slot alone.
+    fn encode(&self, bytes: &mut [u8], sequence: u64) {
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.capacity.to_le_bytes());
+
+        let (fixed, slots) = bytes.split_at_mut(FIXED_LEN);
+        let offset = SLOTS[(sequence % 2) as usize] - FIXED_LEN;
+        let slot = &mut slots[offset..offset + CHECKSUM + 8];
+        slot[..8].copy_from_slice(&sequence.to_le_bytes());
+        slot[8..12].copy_from_slice(&(self.policy as u32).to_le_bytes());
+        slot[16..24].copy_from_slice(&self.head.to_le_bytes());
+        slot[24..32].copy_from_slice(&self.len.to_le_bytes());
+        slot[32..40].copy_from_slice(&self.position.to_le_bytes());
+        let checksum = checksum(&[fixed, &slot[..CHECKSUM]]);
+        slot[CHECKSUM..].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    /// The sequence number of the last state saved in `bytes`, assuming it was completely
+    /// written.
+    fn last_sequence(bytes: &[u8]) -> u64 {
+        u64_at(bytes, SLOTS[0]).max(u64_at(bytes, SLOTS[1]))
+    }
+
+    /// Decodes the header of a file of `len` bytes from its newest valid slot, returning it
+    /// with its sequence number.
+    fn decode(bytes: &[u8], len: usize) -> Result<(Self, u64), Error> {
+        let capacity = (len - HEADER_LEN) as u64;
+        if &bytes[..8] != MAGIC
+            || bytes[8..12] != VERSION.to_le_bytes()
+            || u64_at(bytes, 16) != capacity
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let decode_slot = |offset: usize| -> Option<(Self, u64)> {
+            let slot = &bytes[offset..offset + CHECKSUM + 8];
+            let sequence = u64_at(slot, 0);
+            if u64_at(slot, CHECKSUM) != checksum(&[&bytes[..FIXED_LEN], &slot[..CHECKSUM]])
+                || SLOTS[(sequence % 2) as usize] != offset
+            {
+                return None;
+            }
+            let mut policy = [0; 4];
+            policy.copy_from_slice(&slot[8..12]);
+            let header = Header {
+                policy: OverflowPolicy::try_from(u32::from_le_bytes(policy)).ok()?,
+                capacity,
+                head: u64_at(slot, 16),
+                len: u64_at(slot, 24),
+                position: u64_at(slot, 32),
+            };
+            if header.len > capacity || (header.head >= capacity && capacity > 0) {
+                return None;
+            }
+            Some((header, sequence))
+        };
+        SLOTS
+            .iter()
+            .filter_map(|&offset| decode_slot(offset))
+            .max_by_key(|(_, sequence)| *sequence)
+            .ok_or(Error::InvalidArgument)
+    }
+}
+
+impl RingBuffer<u8> {
+    /// Creates an empty buffer of the specified capacity and `OverflowPolicy`, stored in a new
+    /// file at `path`.
+    ///
+    /// The file is memory-mapped, so the contents reach it without any copy, and its header is
+    /// updated along with them: if the process dies, the file can be reopened as it was after
+    /// the last complete operation. Everything is only guaranteed to survive a crash of the
+    /// whole system after `flush`.
+    ///
+    /// Fails with `Io` if the file already exists or cannot be created.
+    pub fn create_file<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let len = capacity
+            .checked_add(HEADER_LEN)
+            .ok_or(Error::AllocationFailed)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+
+        let init = || -> Result<Self, Error> {
+            file.set_len(len as u64)?;
+            let mut buffer = Self::with_storage(Storage::File(Mapping::new(&file, len)?));
+            buffer.set_policy(policy);
+            buffer.flush()?;
+            Ok(buffer)
+        };
+        init().map_err(|error| {
+            let _ = std::fs::remove_file(path);
+            error
+        })
+    }
+
+    /// Opens a buffer stored in the file at `path` by `create_file`, restoring its capacity,
+    /// policy, read position and contents.
+    ///
+    /// The stats, retention and marks are not stored in the file, and start over.
+    ///
+    /// Fails with `Io` if the file cannot be opened, or with `InvalidArgument` if its header is
+    /// not valid.
+    pub fn open_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| Error::InvalidArgument)?;
+        if len < HEADER_LEN {
+            return Err(Error::InvalidArgument);
+        }
+        let mut mapping = Mapping::new(&file, len)?;
+        let bytes = &mut mapping.bytes_mut()[..HEADER_LEN];
+        let (header, sequence) = Header::decode(bytes, len)?;
+        // Overwrite the other slot right away, as it may have been torn by a crash, so that
+        // the last sequence number is the one of a complete state again.
+        header.encode(bytes, sequence.wrapping_add(1));
+
+        let mut buffer = Self::with_storage(Storage::File(mapping));
+        buffer.policy = header.policy;
+        buffer.head = header.head as usize;
+        buffer.len = header.len as usize;
+        buffer.position = header.position;
+        buffer.stats.high_water_mark = header.len;
+        Ok(buffer)
+    }
+}
+
+impl<T> RingBuffer<T> {
+    /// Writes the current state into the header of a file-backed buffer, so that the file
+    /// can be reopened as is at any time. Called on every change of the state.
+    pub(crate) fn save_header(&mut self) {
+        let header = Header {
+            policy: self.policy,
+            capacity: self.storage.len() as u64,
+            head: self.head as u64,
+            len: self.len as u64,
+            position: self.position,
+        };
+        if let Storage::File(mapping) = &mut self.storage {
+            let bytes = &mut mapping.bytes_mut()[..HEADER_LEN];
+            header.encode(bytes, Header::last_sequence(bytes).wrapping_add(1));
+        }
+    }
+
+    /// Waits for the contents and header of a buffer created by `create_file` to reach the
+    /// disk. Does nothing for other buffers.
+    ///
+    /// Fails with `Io` if writing fails.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Storage::File(mapping) = &self.storage {
+            mapping.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Borrows a nul-terminated path passed through the C ABI.
+fn path<'a>(ptr: *const c_char) -> Result<&'a Path, Error> {
+    if ptr.is_null() {
+        return Err(Error::NullPointer);
+    }
+    let bytes = unsafe { CStr::from_ptr(ptr) }.to_bytes();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(Path::new(std::ffi::OsStr::from_bytes(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        std::str::from_utf8(bytes)
+            .map(Path::new)
+            .map_err(|_| Error::InvalidArgument)
+    }
+}
+
+/// Creates an empty ring buffer of bytes of the specified capacity and `OverflowPolicy`,
+/// stored in a new file at `path`, and stores it in `out`.
+///
+/// The buffer is used with the same functions as any other, and its header is updated along
+/// with its contents. They are only guaranteed to survive a crash of the whole system after
+/// `flush`.
+///
+/// Fails with `Io` if the file already exists or cannot be created, or with
+/// `InvalidArgument` if `policy` is not one of the `OverflowPolicy` values.
+///
+/// It is undefined behaviour to pass an invalid pointer to `path` or `out`.
+#[no_mangle]
+pub extern "C" fn create_file(
+    path: *const c_char,
+    capacity: usize,
+    policy: u32,
+    out: *mut *mut RingBuffer<u8>,
+) -> Status {
+    status(|| {
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        let policy = OverflowPolicy::try_from(policy)?;
+        let buffer = RingBuffer::create_file(self::path(path)?, capacity, policy)?;
+        *out = Box::into_raw(Box::new(buffer));
+        Ok(())
+    })
+}
+
+/// Opens a ring buffer of bytes stored in the file at `path` by `create_file`, restoring its
+/// contents, and stores it in `out`.
+///
+/// Fails with `Io` if the file cannot be opened, or with `InvalidArgument` if its header is
+/// not valid.
+///
+/// It is undefined behaviour to pass an invalid pointer to `path` or `out`.
+#[no_mangle]
+pub extern "C" fn open_file(path: *const c_char, out: *mut *mut RingBuffer<u8>) -> Status {
+    status(|| {
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        *out = Box::into_raw(Box::new(RingBuffer::open_file(self::path(path)?)?));
+        Ok(())
+    })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::mem;
+    use std::process;
+
+    #[test]
+    fn check_reopen() {
+        let path = env::temp_dir().join(format!("ringbuf-test-reopen-{}", process::id()));
+        let mut buffer =
+            RingBuffer::create_file(&path, 8, OverflowPolicy::OverwriteOldest).unwrap();
+        assert!(RingBuffer::create_file(&path, 8, OverflowPolicy::Reject).is_err());
+
+        buffer.push(b"abcdef").unwrap();
+        buffer.skip(2).unwrap();
+        buffer.push(b"ghij").unwrap();
+        buffer.flush().unwrap();
+        drop(buffer);
+
+        let mut buffer = RingBuffer::open_file(&path).unwrap();
+        assert_eq!(buffer.capacity(), 8);
+        assert_eq!(buffer.policy(), OverflowPolicy::OverwriteOldest);
+        assert_eq!(buffer.read_position(), 2);
+        assert_eq!(buffer.peek(8).unwrap(), b"cdefghij");
+
+        buffer.skip(3).unwrap();
+        drop(buffer);
+        let mut buffer = RingBuffer::open_file(&path).unwrap();
+        assert_eq!(buffer.peek(5).unwrap(), b"fghij");
+
+        assert_eq!(
+            buffer.resize(16, crate::ResizePolicy::KeepNewest),
+            Err(Error::InvalidArgument)
+        );
+
+        // Clones live on the heap.
+        let mut clone = buffer.clone();
+        clone.clear();
+        drop(clone);
+        drop(buffer);
+        assert_eq!(RingBuffer::open_file(&path).unwrap().len(), 5);
+
+        // A corrupt slot is skipped, but a file without any valid slot is refused.
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[SLOTS[0] + 24] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(RingBuffer::open_file(&path).unwrap().len(), 5);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[SLOTS[0] + 24] ^= 1;
+        bytes[SLOTS[1] + 24] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            RingBuffer::open_file(&path).err(),
+            Some(Error::InvalidArgument)
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(RingBuffer::open_file(&path), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn check_ffi_policy() {
+        use crate::ffi::default::*;
+        use std::ffi::CString;
+        use std::ptr;
+
+        let path = env::temp_dir().join(format!("ringbuf-test-policy-{}", process::id()));
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let mut buffer = ptr::null_mut();
+        let policy = OverflowPolicy::Reject as u32;
+        assert_eq!(
+            create_file(c_path.as_ptr(), 4, policy, &mut buffer),
+            Status::Ok
+        );
+        let policy = OverflowPolicy::TruncateIncoming as u32;
+        assert_eq!(set_overflow_policy(buffer, policy), Status::Ok);
+        assert_eq!(
+            RingBuffer::open_file(&path).unwrap().policy(),
+            OverflowPolicy::TruncateIncoming
+        );
+
+        let mut sync = ptr::null_mut();
+        assert_eq!(sync_new(buffer, &mut sync), Status::Ok);
+        let policy = OverflowPolicy::DropIncoming as u32;
+        assert_eq!(sync_set_overflow_policy(sync, policy), Status::Ok);
+        assert_eq!(
+            RingBuffer::open_file(&path).unwrap().policy(),
+            OverflowPolicy::DropIncoming
+        );
+        assert_eq!(sync_del(sync), Status::Ok);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn check_reopen_without_drop() {
+        let path = env::temp_dir().join(format!("ringbuf-test-crash-{}", process::id()));
+        let mut buffer = RingBuffer::create_file(&path, 8, OverflowPolicy::Reject).unwrap();
+        buffer.push(b"abcdefgh").unwrap();
+        buffer.flush().unwrap();
+        buffer.skip(6).unwrap();
+        buffer.push(b"ij").unwrap();
+        // Peeking across the end of the storage leaves the file as is.
+        assert_eq!(buffer.peek(4).unwrap(), b"ghij");
+
+        // Reopening while the buffer is still alive, as if the process had died.
+        let mut reopened = RingBuffer::open_file(&path).unwrap();
+        assert_eq!(reopened.read_position(), 6);
+        assert_eq!(reopened.peek(4).unwrap(), b"ghij");
+
+        reopened.skip(1).unwrap();
+        reopened.set_policy(OverflowPolicy::OverwriteOldest);
+        reopened.push(b"klmnopq").unwrap();
+        mem::forget(reopened);
+        let mut reopened = RingBuffer::open_file(&path).unwrap();
+        assert_eq!(reopened.policy(), OverflowPolicy::OverwriteOldest);
+        assert_eq!(reopened.read_position(), 9);
+        assert_eq!(reopened.peek(8).unwrap(), b"jklmnopq");
+
+        // A crash in the middle of an update only loses that update.
+        reopened.skip(2).unwrap();
+        let newest = SLOTS[(Header::last_sequence(&fs::read(&path).unwrap()) % 2) as usize];
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[newest + CHECKSUM] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let mut restored = RingBuffer::open_file(&path).unwrap();
+        assert_eq!(restored.read_position(), 9);
+        assert_eq!(restored.peek(8).unwrap(), b"jklmnopq");
+
+        // Updates go on from the restored state.
+        restored.skip(3).unwrap();
+        mem::forget(restored);
+        assert_eq!(
+            RingBuffer::open_file(&path).unwrap().peek(5).unwrap(),
+            b"mnopq"
+        );
+
+        mem::forget(buffer);
+        mem::forget(reopened);
+        fs::remove_file(&path).unwrap();
+    }
+}
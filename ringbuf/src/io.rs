@@ -0,0 +1,123 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `std::io` support for byte buffers.
+use crate::{Error, OverflowPolicy, RingBuffer};
+use std::io::{self, BufRead, Read, Write};
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::NullPointer | Error::InvalidArgument => io::ErrorKind::InvalidInput,
+            Error::OutOfBounds { .. } => io::ErrorKind::UnexpectedEof,
+            Error::AllocationFailed => io::ErrorKind::OutOfMemory,
+            Error::Full { .. } => io::ErrorKind::WouldBlock,
+            Error::Timeout => io::ErrorKind::TimedOut,
+            Error::Shutdown => io::ErrorKind::BrokenPipe,
+            Error::Evicted { .. } => io::ErrorKind::NotFound,
+            Error::Io(kind) => kind,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error.kind())
+    }
+}
+
+/// Reading consumes the oldest bytes. An empty buffer reads as the end of the file.
+impl Read for RingBuffer<u8> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.pop(buf))
+    }
+}
+
+/// `fill_buf` returns the readable bytes up to where they wrap around the storage, so it
+/// never copies anything.
+impl BufRead for RingBuffer<u8> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.slices(0, self.len).0)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.skip(amt.min(self.len)).unwrap();
+    }
+}
+
+/// Writing pushes according to the `OverflowPolicy`.
+///
+/// With `OverwriteOldest` every byte is always written. With `TruncateIncoming` and
+/// `DropIncoming` only the stored bytes count as written, so `write_all` fails with
+/// `WriteZero` once the buffer is full. With `Reject`, writing more than fits fails with
+/// `WouldBlock`.
+///
+/// Flushing writes buffers created by `create_file` to disk.
+impl Write for RingBuffer<u8> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let pushed = self.push(buf)?;
+        Ok(match self.policy {
+            OverflowPolicy::OverwriteOldest => buf.len(),
+            _ => pushed.stored,
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(RingBuffer::flush(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_read_write() {
+        let mut buffer = RingBuffer::new(8).unwrap();
+        buffer.write_all(b"abcdef").unwrap();
+        let mut out = [0; 4];
+        assert_eq!(buffer.read(&mut out).unwrap(), 4);
+        assert_eq!(&out, b"abcd");
+
+        // Wrap around the end of the storage.
+        buffer.write_all(b"ghijk").unwrap();
+        let mut out = Vec::new();
+        buffer.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"efghijk");
+        assert_eq!(buffer.read(&mut [0; 4]).unwrap(), 0);
+
+        buffer.set_policy(OverflowPolicy::TruncateIncoming);
+        let error = buffer.write_all(b"0123456789").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(buffer.len(), 8);
+
+        buffer.set_policy(OverflowPolicy::Reject);
+        let error = buffer.write(b"x").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn check_buf_read() {
+        let mut buffer = RingBuffer::new(8).unwrap();
+        buffer.write_all(b"xxxxxx").unwrap();
+        buffer.skip(6).unwrap();
+        buffer.write_all(b"one\ntwo\n").unwrap();
+
+        // The first line wraps around the end of the storage.
+        assert_eq!(buffer.fill_buf().unwrap(), b"on");
+        let mut line = String::new();
+        buffer.read_line(&mut line).unwrap();
+        assert_eq!(line, "one\n");
+
+        let mut rest = Vec::new();
+        io::copy(&mut buffer, &mut rest).unwrap();
+        assert_eq!(rest, b"two\n");
+        assert!(buffer.is_empty());
+    }
+}
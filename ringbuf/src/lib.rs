@@ -5,155 +5,1361 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use std::collections::VecDeque;
 
-pub struct RingBuffer {
-    queue: VecDeque<u8>,
-    // `VecDeque` doesn't use the exact capacity we pass to it, so we need this field.
-    capacity: usize,
+//! A fixed-capacity ring buffer (circular queue), usable from Rust and through a C ABI.
+//!
+//! ```
+//! use ringbuf::{OverflowPolicy, RingBuffer};
+//!
+//! let mut buffer = RingBuffer::with_policy(4, OverflowPolicy::OverwriteOldest).unwrap();
+//! buffer.push(b"abcdef").unwrap();
+//! assert_eq!(buffer.peek(4).unwrap(), b"cdef");
+//! buffer.skip(2).unwrap();
+//! assert_eq!(buffer.len(), 2);
+//! ```
+
+// The exported functions are meant to be called through the C ABI, and they document which
+// pointers they expect instead of being marked as `unsafe`.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+mod broadcast;
+mod ffi;
+mod file;
+mod io;
+mod record;
+mod shm;
+mod snapshot;
+mod spsc;
+mod storage;
+mod sync;
+mod sys;
+
+pub use broadcast::{BroadcastReader, BroadcastWriter};
+pub use record::RecordRing;
+pub use shm::SharedRing;
+pub use snapshot::Element;
+pub use spsc::{channel, Consumer, Producer};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::FromIterator;
+use storage::Storage;
+pub use sync::{Guard, SyncRingBuffer};
+
+/// Errors that can occur when operating on a `RingBuffer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A null pointer was passed where a valid one was expected.
+    NullPointer,
+    /// Attempted to access more data than available in the buffer.
+    OutOfBounds { requested: usize, available: usize },
+    /// The memory for the buffer could not be allocated.
+    AllocationFailed,
+    /// An argument had a value outside of its valid range.
+    InvalidArgument,
+    /// Attempted to push more data than fits, with a policy that rejects it.
+    Full { requested: usize, available: usize },
+    /// Waiting on the buffer took longer than allowed.
+    Timeout,
+    /// Waiting on the buffer was interrupted because it was shut down.
+    Shutdown,
+    /// Attempted to access a position which is not in the buffer anymore.
+    Evicted { requested: u64, oldest: u64 },
+    /// A file or shared memory segment backing a buffer could not be accessed.
+    Io(std::io::ErrorKind),
 }
 
-impl RingBuffer {
-    fn new(capacity: usize) -> Self {
-        RingBuffer {
-            // There is no `with_exact_capacity`, and `reserve_exact` just calls `reserve`,
-            // so there's no point in trying to fight the excess capacity.
-            queue: VecDeque::with_capacity(capacity),
-            capacity,
+impl Error {
+    fn status(&self) -> Status {
+        match self {
+            Error::NullPointer => Status::NullPointer,
+            Error::OutOfBounds { .. } => Status::OutOfBounds,
+            Error::AllocationFailed => Status::AllocationFailed,
+            Error::InvalidArgument => Status::InvalidArgument,
+            Error::Full { .. } => Status::Full,
+            Error::Timeout => Status::Timeout,
+            Error::Shutdown => Status::Shutdown,
+            Error::Evicted { .. } => Status::Evicted,
+            Error::Io(_) => Status::Io,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NullPointer => write!(f, "null pointer"),
+            Error::OutOfBounds {
+                requested,
+                available,
+            } => write!(
+                f,
+                "cannot access {} elements, only {} are available",
+                requested, available
+            ),
+            Error::AllocationFailed => write!(f, "memory allocation failed"),
+            Error::InvalidArgument => write!(f, "invalid argument"),
+            Error::Full {
+                requested,
+                available,
+            } => write!(
+                f,
+                "cannot push {} elements, only {} fit",
+                requested, available
+            ),
+            Error::Timeout => write!(f, "timed out"),
+            Error::Shutdown => write!(f, "buffer was shut down"),
+            Error::Evicted { requested, oldest } => write!(
+                f,
+                "position {} was evicted, the oldest available is {}",
+                requested, oldest
+            ),
+            Error::Io(kind) => write!(f, "I/O error: {}", kind),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Status code returned by the exported functions.
+///
+/// Anything other than `Ok` means the operation failed and did not modify the buffer.
+/// A description of the failure can be obtained with `last_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    NullPointer = 1,
+    OutOfBounds = 2,
+    AllocationFailed = 3,
+    InvalidArgument = 4,
+    Full = 5,
+    Timeout = 6,
+    Shutdown = 7,
+    Evicted = 8,
+    Io = 9,
+}
+
+/// What to do when pushing more data than the buffer has room for.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Drop the oldest elements to make room for the new ones.
+    #[default]
+    OverwriteOldest = 0,
+    /// Fail with `Error::Full` without storing anything.
+    Reject = 1,
+    /// Store as many of the new elements as fit, and drop the rest.
+    TruncateIncoming = 2,
+    /// Keep the old elements, and drop all of the new ones.
+    DropIncoming = 3,
+}
+
+impl TryFrom<u32> for OverflowPolicy {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(OverflowPolicy::OverwriteOldest),
+            1 => Ok(OverflowPolicy::Reject),
+            2 => Ok(OverflowPolicy::TruncateIncoming),
+            3 => Ok(OverflowPolicy::DropIncoming),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Which elements to keep when shrinking a buffer below its length.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizePolicy {
+    /// Drop the oldest elements.
+    #[default]
+    KeepNewest = 0,
+    /// Drop the newest elements.
+    KeepOldest = 1,
+}
+
+impl TryFrom<u32> for ResizePolicy {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(ResizePolicy::KeepNewest),
+            1 => Ok(ResizePolicy::KeepOldest),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// The outcome of pushing data to a buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pushed {
+    /// How many of the new elements were stored.
+    pub stored: usize,
+    /// How many elements were lost, either old ones that got overwritten or new ones that
+    /// did not fit.
+    pub dropped: usize,
+}
+
+/// Running counters of the data that went through a buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// Elements stored by pushes, minus the newest ones dropped by shrinking.
+    pub pushed: u64,
+    /// Elements consumed by skips, minus those made readable again by rewinding.
+    pub consumed: u64,
+    /// Stored elements that got overwritten, or dropped from the start by shrinking, before
+    /// being consumed.
+    pub overwritten: u64,
+    /// New elements that were dropped because they did not fit, including the newest ones
+    /// dropped by shrinking.
+    pub dropped: u64,
+    /// Number of pushes that did not fit, including the rejected ones.
+    pub overflows: u64,
+    /// Highest number of readable elements there has been at once.
+    pub high_water_mark: u64,
+}
+
+/// A fixed-capacity queue of `T` elements, `u8` by default.
+///
+/// The buffer never grows: pushing past its capacity does what its `OverflowPolicy` says.
+pub struct RingBuffer<T = u8> {
+    // Exactly `capacity` elements, only ever reallocated by `resize`.
+    storage: Storage<T>,
+    // Index of the oldest element in `storage`.
+    head: usize,
+    // Number of readable elements, starting at `head` and wrapping around the end of `storage`.
+    len: usize,
+    // Position of the element at `head` in the stream of every element ever pushed.
+    position: u64,
+    // Maximum number of consumed elements to keep track of for `rewind`.
+    retention: usize,
+    // Number of consumed elements right before `head` which have not been overwritten yet,
+    // up to `retention`.
+    retained: usize,
+    // Stream positions saved with `mark`.
+    marks: HashMap<String, u64>,
+    policy: OverflowPolicy,
+    stats: Stats,
+    // Copies of the elements returned by `peek` when they wrap around the end of `storage`.
+    scratch: Vec<T>,
+}
+
+impl<T: Copy + Default> RingBuffer<T> {
+    /// Creates an empty buffer of the specified capacity, which overwrites the oldest
+    /// elements when full.
+    ///
+    /// Fails with `AllocationFailed` if the memory cannot be allocated.
+    pub fn new(capacity: usize) -> Result<Self, Error> {
+        Ok(Self::with_storage(Storage::heap(capacity)?))
+    }
+
+    /// Creates an empty buffer of the specified capacity and `OverflowPolicy`.
+    pub fn with_policy(capacity: usize, policy: OverflowPolicy) -> Result<Self, Error> {
+        let mut buffer = Self::new(capacity)?;
+        buffer.policy = policy;
+        Ok(buffer)
+    }
+
+    /// Creates a buffer whose memory is mapped twice in a row, so that peeking never needs
+    /// to copy the elements.
+    ///
+    /// The capacity is rounded up for the memory to be a multiple of the page size. If the
+    /// memory cannot be mapped, a regular buffer with the same capacity is created instead.
+    pub fn new_mirrored(capacity: usize) -> Result<Self, Error> {
+        Ok(Self::with_storage(Self::mirrored_storage(capacity)?))
+    }
+
+    fn mirrored_storage(capacity: usize) -> Result<Storage<T>, Error> {
+        let granularity = Storage::<T>::mirror_granularity();
+        let capacity = capacity
+            .checked_add(granularity - 1)
+            .ok_or(Error::AllocationFailed)?
+            / granularity
+            * granularity;
+
+        match Storage::mirrored(capacity) {
+            Ok(storage) => Ok(storage),
+            Err(_) => Storage::heap(capacity),
         }
     }
 
-    fn peek(&mut self, n: usize) -> &[u8] {
-        if n > self.queue.len() {
-            panic!("oob");
+    /// Changes the capacity of the buffer, keeping its contents, and returns how many
+    /// elements were dropped because they didn't fit anymore. The `ResizePolicy` decides
+    /// which ones.
+    ///
+    /// The memory is reallocated, even when the capacity doesn't change. The capacity of
+    /// mirrored buffers is rounded up just like in `new_mirrored`.
+    ///
+    /// Dropping the newest elements moves the `write_position` back, as if they had been
+    /// dropped instead of pushed, and they are counted as such in the `Stats`. Consumed
+    /// elements cannot be rewound to afterwards.
+    ///
+    /// Fails with `AllocationFailed` if the new memory cannot be allocated, or with
+    /// `InvalidArgument` if the buffer is stored in a file, leaving the buffer untouched.
+    pub fn resize(&mut self, capacity: usize, policy: ResizePolicy) -> Result<usize, Error> {
+        if self.storage.is_file() {
+            return Err(Error::InvalidArgument);
         }
+        let mut storage = if self.storage.is_mirrored() {
+            Self::mirrored_storage(capacity)?
+        } else {
+            Storage::heap(capacity)?
+        };
 
-        let need_contiguous = {
-            let (left, _) = self.queue.as_slices();
-            n > left.len()
+        let kept = self.len.min(storage.len());
+        let dropped = self.len - kept;
+        let offset = match policy {
+            ResizePolicy::KeepNewest => dropped,
+            ResizePolicy::KeepOldest => 0,
         };
+        self.copy_from(offset, &mut storage[..kept]);
 
-        if need_contiguous {
-            self.queue.make_contiguous();
+        self.storage = storage;
+        self.head = 0;
+        self.len = kept;
+        self.retained = 0;
+        self.position += offset as u64;
+        match policy {
+            ResizePolicy::KeepNewest => self.stats.overwritten += dropped as u64,
+            ResizePolicy::KeepOldest => {
+                // The counters may have been reset since the elements were pushed.
+                self.stats.pushed = self.stats.pushed.saturating_sub(dropped as u64);
+                self.stats.dropped += dropped as u64;
+            }
         }
+        Ok(dropped)
+    }
+}
 
-        let (left, _) = self.queue.as_slices();
-        &left[..n]
+impl<T: Copy> RingBuffer<T> {
+    fn with_storage(storage: Storage<T>) -> Self {
+        RingBuffer {
+            storage,
+            head: 0,
+            len: 0,
+            position: 0,
+            retention: 0,
+            retained: 0,
+            marks: HashMap::new(),
+            policy: OverflowPolicy::default(),
+            stats: Stats::default(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// How many elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// How many elements can be read from the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Is there nothing to read from the buffer?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The position of the oldest readable element in the stream of every element ever
+    /// pushed, which only ever grows.
+    pub fn read_position(&self) -> u64 {
+        self.position
+    }
+
+    /// The position the next pushed element will have in the stream of every element ever
+    /// pushed.
+    pub fn write_position(&self) -> u64 {
+        self.position + self.len as u64
+    }
+
+    /// Is the buffer full, so that pushing anything overflows?
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    /// Is the buffer memory mapped twice in a row?
+    pub fn is_mirrored(&self) -> bool {
+        self.storage.is_mirrored()
     }
 
-    fn skip(&mut self, n: usize) {
-        if n > self.queue.len() {
-            panic!("oob");
+    /// The `OverflowPolicy` used when pushing past the capacity.
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Changes the `OverflowPolicy` used from now on.
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+        self.save_header();
+    }
+
+    /// The running counters of the data that went through the buffer.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Wraps an index in the range `0..2 * capacity` back into `storage`.
+    fn wrap(&self, index: usize) -> usize {
+        if index >= self.capacity() {
+            index - self.capacity()
+        } else {
+            index
         }
+    }
 
-        self.queue.drain(..n);
+    fn check_available(&self, n: usize) -> Result<(), Error> {
+        if n > self.len {
+            Err(Error::OutOfBounds {
+                requested: n,
+                available: self.len,
+            })
+        } else {
+            Ok(())
+        }
     }
 
-    fn push(&mut self, bytes: &[u8]) {
-        let leeway = self.capacity - self.queue.len();
+    /// Returns `n` readable elements starting `offset` elements in, split where they wrap
+    /// around the storage. They must all be available.
+    fn slices(&self, offset: usize, n: usize) -> (&[T], &[T]) {
+        let start = self.wrap(self.head + offset);
+        let first = n.min(self.capacity() - start);
+        (
+            &self.storage[start..start + first],
+            &self.storage[..n - first],
+        )
+    }
+
+    /// Copies the available elements starting `offset` elements in into `dst`.
+    fn copy_from(&self, offset: usize, dst: &mut [T]) {
+        let (first, second) = self.slices(offset, dst.len());
+        let (left, right) = dst.split_at_mut(first.len());
+        left.copy_from_slice(first);
+        right.copy_from_slice(second);
+    }
+
+    /// Returns the first `n` elements, without consuming them.
+    ///
+    /// This takes `&mut self` because, unless the buffer is mirrored, elements which wrap
+    /// around the end of the storage are copied to scratch memory to be contiguous. The
+    /// contents themselves never move.
+    ///
+    /// Fails with `OutOfBounds` if one tries to read more than `len` elements.
+    pub fn peek(&mut self, n: usize) -> Result<&[T], Error> {
+        self.check_available(n)?;
+
+        if self.head + n > self.capacity() && !self.storage.is_mirrored() {
+            let mut scratch = std::mem::take(&mut self.scratch);
+            let (first, second) = self.slices(0, n);
+            scratch.clear();
+            scratch.extend_from_slice(first);
+            scratch.extend_from_slice(second);
+            self.scratch = scratch;
+            return Ok(&self.scratch);
+        }
+
+        Ok(&self.storage.window()[self.head..self.head + n])
+    }
 
-        if bytes.len() <= leeway {
-            // There's enough leeway to insert all bytes.
-            self.queue.extend(bytes);
-        } else if bytes.len() >= self.capacity {
-            // Not enough room to fit everything, drop all contents and extend from the tail.
-            self.queue.clear();
-            self.queue.extend(&bytes[bytes.len() - self.capacity..]);
+    /// Returns the first `n` elements as up to two slices, without consuming them. The second
+    /// slice is empty unless the elements wrap around the end of the storage.
+    ///
+    /// Unlike `peek`, this never copies anything.
+    ///
+    /// Fails with `OutOfBounds` if one tries to read more than `len` elements.
+    pub fn as_slices(&self, n: usize) -> Result<(&[T], &[T]), Error> {
+        self.check_available(n)?;
+        Ok(self.slices(0, n))
+    }
+
+    /// Copies up to `dst.len()` of the first elements into `dst`, without consuming them,
+    /// and returns how many were copied.
+    pub fn peek_into(&self, dst: &mut [T]) -> usize {
+        let n = dst.len().min(self.len);
+        self.copy_from(0, &mut dst[..n]);
+        n
+    }
+
+    /// Copies `dst.len()` elements starting `offset` elements in into `dst`, without
+    /// consuming anything.
+    ///
+    /// Fails with `OutOfBounds` if the range goes past the `len` readable elements.
+    pub fn peek_at(&self, offset: usize, dst: &mut [T]) -> Result<(), Error> {
+        self.check_available(offset.saturating_add(dst.len()))?;
+        self.copy_from(offset, dst);
+        Ok(())
+    }
+
+    /// Copies `dst.len()` elements starting at the absolute `position` into `dst`, without
+    /// consuming anything.
+    ///
+    /// Fails with `Evicted` if the position is before the `read_position`, or with
+    /// `OutOfBounds` if the range goes past the `write_position`.
+    pub fn peek_at_position(&self, position: u64, dst: &mut [T]) -> Result<(), Error> {
+        let offset = position.checked_sub(self.position).ok_or(Error::Evicted {
+            requested: position,
+            oldest: self.position,
+        })?;
+        self.peek_at(usize::try_from(offset).unwrap_or(usize::MAX), dst)
+    }
+
+    /// Returns the readable element `index` elements in, if there is one.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            Some(&self.storage[self.wrap(self.head + index)])
         } else {
-            // Make enough room to fit everything.
-            self.queue.drain(..bytes.len() - leeway);
-            self.queue.extend(bytes);
+            None
         }
     }
-}
 
-/// Creates a new ring buffer of the specified capacity.
-#[no_mangle]
-pub extern "C" fn new(capacity: usize) -> *mut RingBuffer {
-    Box::into_raw(Box::new(RingBuffer::new(capacity)))
-}
+    /// Copies the first `dst.len()` elements into `dst`, without consuming them.
+    fn copy_out(&self, dst: &mut [T]) -> Result<(), Error> {
+        self.peek_at(0, dst)
+    }
 
-/// How much data can be read from the buffer?
-///
-/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RingBuffer`.
-#[no_mangle]
-pub extern "C" fn read_available(buffer: *mut RingBuffer) -> usize {
-    let buffer = unsafe { &mut *buffer };
-    buffer.queue.len()
+    /// Consumes the first `n` elements.
+    ///
+    /// Fails with `OutOfBounds` if one tries to skip more than `len` elements.
+    pub fn skip(&mut self, n: usize) -> Result<(), Error> {
+        self.check_available(n)?;
+        self.consume(n);
+        Ok(())
+    }
+
+    /// Consumes the first `n` elements, which must be available.
+    fn consume(&mut self, n: usize) {
+        self.evict(n);
+        self.retained = (self.retained + n)
+            .min(self.retention)
+            .min(self.capacity() - self.len);
+        self.stats.consumed += n as u64;
+    }
+
+    /// Drops the first `n` elements, which must be available.
+    fn evict(&mut self, n: usize) {
+        self.head = self.wrap(self.head + n);
+        self.len -= n;
+        self.position += n as u64;
+        self.save_header();
+    }
+
+    /// Copies the first `dst.len()` elements into `dst` and consumes them.
+    ///
+    /// Fails with `OutOfBounds`, without consuming anything, if one tries to pop more than
+    /// `len` elements.
+    pub fn pop_exact(&mut self, dst: &mut [T]) -> Result<(), Error> {
+        self.copy_out(dst)?;
+        self.skip(dst.len())
+    }
+
+    /// Copies up to `dst.len()` of the first elements into `dst`, consumes them, and returns
+    /// how many were popped.
+    pub fn pop(&mut self, dst: &mut [T]) -> usize {
+        let n = self.peek_into(dst);
+        self.consume(n);
+        n
+    }
+
+    /// Returns the offset of the first occurrence of `pattern` in the readable elements, if
+    /// any.
+    ///
+    /// The search works across the end of the storage without moving the contents around.
+    ///
+    /// Fails with `InvalidArgument` if `pattern` is empty.
+    pub fn find(&self, pattern: &[T]) -> Result<Option<usize>, Error>
+    where
+        T: PartialEq,
+    {
+        if pattern.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+        let (first, second) = self.slices(0, self.len);
+        let at = |i: usize| {
+            if i < first.len() {
+                &first[i]
+            } else {
+                &second[i - first.len()]
+            }
+        };
+
+        let last = match self.len.checked_sub(pattern.len()) {
+            Some(last) => last,
+            None => return Ok(None),
+        };
+        Ok((0..=last).find(|&start| {
+            pattern
+                .iter()
+                .enumerate()
+                .all(|(i, element)| at(start + i) == element)
+        }))
+    }
+
+    /// Pops the elements up to and including the first occurrence of `delimiter` into `dst`,
+    /// and returns how many were popped.
+    ///
+    /// Returns `None`, without consuming anything, unless the delimiter ends within the first
+    /// `dst.len()` elements. This is the equivalent of `BufRead::read_until` for multi-element
+    /// delimiters.
+    ///
+    /// Fails with `InvalidArgument` if `delimiter` is empty.
+    pub fn pop_until(&mut self, delimiter: &[T], dst: &mut [T]) -> Result<Option<usize>, Error>
+    where
+        T: PartialEq,
+    {
+        let n = match self.find(delimiter)? {
+            Some(offset) => offset + delimiter.len(),
+            None => return Ok(None),
+        };
+        if n > dst.len() {
+            return Ok(None);
+        }
+        self.pop(&mut dst[..n]);
+        Ok(Some(n))
+    }
+
+    /// Consumes every element.
+    pub fn clear(&mut self) {
+        self.consume(self.len);
+    }
+
+    /// How many consumed elements are kept track of for `rewind`, 0 by default.
+    pub fn retention(&self) -> usize {
+        self.retention
+    }
+
+    /// Changes how many consumed elements are kept track of for `rewind`.
+    ///
+    /// Consumed elements are kept in memory until pushes overwrite them, regardless of the
+    /// retention. Only those consumed from now on can be rewound to, unless the retention
+    /// shrinks.
+    pub fn set_retention(&mut self, retention: usize) {
+        self.retention = retention;
+        self.retained = self.retained.min(retention);
+    }
+
+    /// How many consumed elements can currently be rewound to.
+    pub fn retained(&self) -> usize {
+        self.retained
+    }
+
+    /// Makes the last `n` consumed elements readable again.
+    ///
+    /// Fails with `Evicted` if they are beyond the `retention` or were overwritten already.
+    pub fn rewind(&mut self, n: usize) -> Result<(), Error> {
+        if n > self.retained {
+            return Err(Error::Evicted {
+                requested: self.position.saturating_sub(n as u64),
+                oldest: self.position - self.retained as u64,
+            });
+        }
+
+        self.head = self.wrap(self.head + self.capacity() - n);
+        self.len += n;
+        self.position -= n as u64;
+        self.retained -= n;
+        // The counters may have been reset since the elements were consumed.
+        self.stats.consumed = self.stats.consumed.saturating_sub(n as u64);
+        self.save_header();
+        self.stats.high_water_mark = self.stats.high_water_mark.max(self.len as u64);
+        Ok(())
+    }
+
+    /// Saves the current `read_position` under `name`, replacing any previous mark with the
+    /// same name.
+    pub fn mark(&mut self, name: &str) {
+        self.marks.insert(name.to_owned(), self.position);
+    }
+
+    /// Forgets the mark saved under `name`, and returns its position.
+    pub fn remove_mark(&mut self, name: &str) -> Option<u64> {
+        self.marks.remove(name)
+    }
+
+    /// Moves the `read_position` to the mark saved under `name`, rewinding or skipping as
+    /// needed.
+    ///
+    /// Fails with `InvalidArgument` if there is no such mark, with `Evicted` if rewinding
+    /// that far is not possible, or with `OutOfBounds` if skipping that far is not possible.
+    pub fn seek_to_mark(&mut self, name: &str) -> Result<(), Error> {
+        let position = *self.marks.get(name).ok_or(Error::InvalidArgument)?;
+        match position.checked_sub(self.position) {
+            Some(n) => self.skip(usize::try_from(n).unwrap_or(usize::MAX)),
+            None => {
+                let n = self.position - position;
+                self.rewind(usize::try_from(n).unwrap_or(usize::MAX))
+            }
+        }
+    }
+
+    /// Resets the running counters. The high-water mark starts over from `len`.
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats {
+            high_water_mark: self.len as u64,
+            ..Stats::default()
+        };
+    }
+
+    /// Pushes `data` to the end of the buffer, returning how many elements were stored and
+    /// dropped.
+    ///
+    /// What happens when the data doesn't fit depends on the `OverflowPolicy`. With
+    /// `Reject`, the push fails with `Full` and nothing is stored.
+    pub fn push(&mut self, data: &[T]) -> Result<Pushed, Error> {
+        let (room, evicted) = self.make_room(data.len())?;
+        let stored = match self.policy {
+            // Only the last elements could possibly remain in the buffer.
+            OverflowPolicy::OverwriteOldest => &data[data.len() - room..],
+            _ => &data[..room],
+        };
+
+        let (first, second) = self.free_slices(room);
+        let (left, right) = stored.split_at(first.len());
+        first.copy_from_slice(left);
+        second.copy_from_slice(right);
+        self.publish(room);
+
+        Ok(Pushed {
+            stored: room,
+            dropped: data.len() - room + evicted,
+        })
+    }
+
+    /// Makes room for `n` more elements at the end of the buffer, and returns the writable
+    /// memory as up to two slices, to be filled and then published with `commit`.
+    ///
+    /// What happens when the elements don't fit is the same as for `push`: with
+    /// `OverwriteOldest` the oldest elements are dropped right away, while with
+    /// `TruncateIncoming` and `DropIncoming` the slices are shorter than `n`. The elements
+    /// that cannot be stored count as dropped even if they are never written.
+    pub fn reserve(&mut self, n: usize) -> Result<(&mut [T], &mut [T]), Error> {
+        let (room, _) = self.make_room(n)?;
+        Ok(self.free_slices(room))
+    }
+
+    /// Publishes the first `n` elements written into the slices returned by `reserve`.
+    ///
+    /// Fails with `OutOfBounds` if there is no room for `n` more elements.
+    pub fn commit(&mut self, n: usize) -> Result<(), Error> {
+        let leeway = self.capacity() - self.len;
+        if n > leeway {
+            return Err(Error::OutOfBounds {
+                requested: n,
+                available: leeway,
+            });
+        }
+        self.publish(n);
+        Ok(())
+    }
+
+    /// Applies the `OverflowPolicy` to make room for `n` more elements, and returns how many
+    /// of them can be stored and how many old elements were evicted.
+    fn make_room(&mut self, n: usize) -> Result<(usize, usize), Error> {
+        let leeway = self.capacity() - self.len;
+        if n <= leeway {
+            // There's enough leeway to insert everything.
+            return Ok((n, 0));
+        }
+
+        self.stats.overflows += 1;
+        let (room, evicted) = match self.policy {
+            OverflowPolicy::OverwriteOldest => {
+                // Make room for up to `capacity` elements by dropping the oldest ones.
+                let room = n.min(self.capacity());
+                let evicted = room - leeway;
+                self.evict(evicted);
+                // The new elements that don't fit are as good as pushed and overwritten, so
+                // positions keep matching the incoming stream.
+                self.position += (n - room) as u64;
+                self.save_header();
+                (room, evicted)
+            }
+            OverflowPolicy::Reject => {
+                return Err(Error::Full {
+                    requested: n,
+                    available: leeway,
+                })
+            }
+            OverflowPolicy::TruncateIncoming => (leeway, 0),
+            OverflowPolicy::DropIncoming => (0, 0),
+        };
+
+        self.stats.overwritten += evicted as u64;
+        self.stats.dropped += (n - room) as u64;
+        Ok((room, evicted))
+    }
+
+    /// Returns the first `n` free elements after the readable ones, split where they wrap
+    /// around the storage. There must be room for them.
+    fn free_slices(&mut self, n: usize) -> (&mut [T], &mut [T]) {
+        // The consumed elements right before `head` are the last to be overwritten.
+        self.retained = self.retained.min(self.capacity() - self.len - n);
+
+        let tail = self.wrap(self.head + self.len);
+        let first = n.min(self.capacity() - tail);
+        let (left, right) = self.storage.split_at_mut(tail);
+        (&mut right[..first], &mut left[..n - first])
+    }
+
+    /// Makes the `n` free elements after the readable ones readable.
+    fn publish(&mut self, n: usize) {
+        // Committing without reserving first still overwrites consumed elements.
+        self.retained = self.retained.min(self.capacity() - self.len - n);
+        self.len += n;
+        self.save_header();
+        self.stats.pushed += n as u64;
+        self.stats.high_water_mark = self.stats.high_water_mark.max(self.len as u64);
+    }
 }
 
-/// How much data can be written into the buffer without overwriting contents?
-///
-/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RingBuffer`.
-#[no_mangle]
-pub extern "C" fn write_available(buffer: *mut RingBuffer) -> usize {
-    let buffer = unsafe { &mut *buffer };
-    buffer.capacity - buffer.queue.len()
+impl<T: Copy + Default> Clone for RingBuffer<T> {
+    fn clone(&self) -> Self {
+        RingBuffer {
+            storage: self.storage.clone(),
+            head: self.head,
+            len: self.len,
+            position: self.position,
+            retention: self.retention,
+            retained: self.retained,
+            marks: self.marks.clone(),
+            policy: self.policy,
+            stats: self.stats,
+            scratch: Vec::new(),
+        }
+    }
 }
 
-/// Peeks from the buffer.
-///
-/// Panics if one tries to read more than available in the buffer.
-///
-/// The results should **not** be read from after pushing or deleting the buffer.
-///
-/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RingBuffer`.
-#[no_mangle]
-pub extern "C" fn peek(buffer: *mut RingBuffer, n: usize) -> *const u8 {
-    let buffer = unsafe { &mut *buffer };
-    buffer.peek(n).as_ptr()
+impl<T: Copy + fmt::Debug> fmt::Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (first, second) = self.slices(0, self.len);
+        f.debug_struct("RingBuffer")
+            .field("capacity", &self.capacity())
+            .field("policy", &self.policy)
+            .field("contents", &first.iter().chain(second).collect::<Vec<_>>())
+            .finish()
+    }
 }
 
-/// Skips data from the buffer.
-///
-/// Panics if one tries to skip more than available in the buffer.
-///
-/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RingBuffer`.
-#[no_mangle]
-pub extern "C" fn skip(buffer: *mut RingBuffer, n: usize) {
-    let buffer = unsafe { &mut *buffer };
-    buffer.skip(n)
+/// Pushes the elements one by one, so with `OverflowPolicy::Reject` those that don't fit are
+/// discarded.
+impl<T: Copy> Extend<T> for RingBuffer<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for element in iter {
+            let _ = self.push(&[element]);
+        }
+    }
 }
 
-/// Pushes data to the buffer.
-///
-/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RingBuffer`,
-/// or to pass an invalid pointer to bytes which is not of the matching length.
-#[no_mangle]
-pub extern "C" fn push(buffer: *mut RingBuffer, bytes: *const u8, n: usize) {
-    let buffer = unsafe { &mut *buffer };
-    let bytes = unsafe { std::slice::from_raw_parts(bytes, n) };
-    buffer.push(bytes)
+impl<'a, T: Copy + 'a> Extend<&'a T> for RingBuffer<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
 }
 
-/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RingBuffer`.
-#[no_mangle]
-pub extern "C" fn del(buffer: *mut RingBuffer) {
-    let buffer = unsafe { Box::from_raw(buffer) };
-    drop(buffer);
+/// Creates a full buffer, with a capacity of exactly as many elements as collected.
+impl<T: Copy + Default> FromIterator<T> for RingBuffer<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let elements = iter.into_iter().collect::<Vec<_>>();
+        let mut buffer = Self::new(elements.len()).expect("failed to allocate a ring buffer");
+        buffer.push(&elements).unwrap();
+        buffer
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn contents<T: Copy>(buffer: &mut RingBuffer<T>) -> Vec<T> {
+        let n = buffer.len;
+        buffer.peek(n).unwrap().to_vec()
+    }
+
     #[test]
     fn check_push_paths() {
-        let mut buffer = RingBuffer::new(4);
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
 
         // Enough room.
-        buffer.push(&[1, 2, 3]);
-        assert_eq!(buffer.queue.len(), 3);
-        assert_eq!(buffer.queue, &[1, 2, 3]);
+        buffer.push(&[1, 2, 3]).unwrap();
+        assert_eq!(buffer.len, 3);
+        assert_eq!(contents(&mut buffer), &[1, 2, 3]);
 
         // Not enough room.
-        buffer.push(&[1, 2, 3]);
-        assert_eq!(buffer.queue.len(), 4);
-        assert_eq!(buffer.queue, &[3, 1, 2, 3]);
+        buffer.push(&[1, 2, 3]).unwrap();
+        assert_eq!(buffer.len, 4);
+        assert_eq!(contents(&mut buffer), &[3, 1, 2, 3]);
 
         // Not enough room or capacity.
-        buffer.push(&[1, 2, 3, 4, 5]);
-        assert_eq!(buffer.queue.len(), 4);
-        assert_eq!(buffer.queue, &[2, 3, 4, 5]);
+        buffer.push(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(buffer.len, 4);
+        assert_eq!(contents(&mut buffer), &[2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn check_overflow_policies() {
+        let pushed = |stored, dropped| Ok(Pushed { stored, dropped });
+
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        buffer.push(&[1, 2, 3]).unwrap();
+        assert_eq!(buffer.push(&[4, 5]), pushed(2, 1));
+        assert_eq!(buffer.push(&[6, 7, 8, 9, 10]), pushed(4, 5));
+        assert_eq!(contents(&mut buffer), &[7, 8, 9, 10]);
+
+        let mut buffer = RingBuffer::<u8>::with_policy(4, OverflowPolicy::Reject).unwrap();
+        buffer.push(&[1, 2, 3]).unwrap();
+        assert_eq!(
+            buffer.push(&[4, 5]),
+            Err(Error::Full {
+                requested: 2,
+                available: 1
+            })
+        );
+        assert_eq!(contents(&mut buffer), &[1, 2, 3]);
+
+        let mut buffer =
+            RingBuffer::<u8>::with_policy(4, OverflowPolicy::TruncateIncoming).unwrap();
+        buffer.push(&[1, 2, 3]).unwrap();
+        assert_eq!(buffer.push(&[4, 5]), pushed(1, 1));
+        assert_eq!(contents(&mut buffer), &[1, 2, 3, 4]);
+
+        let mut buffer = RingBuffer::<u8>::with_policy(4, OverflowPolicy::DropIncoming).unwrap();
+        buffer.push(&[1, 2, 3]).unwrap();
+        assert_eq!(buffer.push(&[4, 5]), pushed(0, 2));
+        assert_eq!(buffer.push(&[4]), pushed(1, 0));
+        assert_eq!(contents(&mut buffer), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn check_stats() {
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        buffer.push(&[1, 2, 3]).unwrap();
+        buffer.skip(1).unwrap();
+        buffer.push(&[4, 5, 6]).unwrap();
+        buffer.policy = OverflowPolicy::Reject;
+        buffer.push(&[7]).unwrap_err();
+        buffer.policy = OverflowPolicy::TruncateIncoming;
+        buffer.skip(1).unwrap();
+        buffer.push(&[8, 9]).unwrap();
+
+        assert_eq!(
+            buffer.stats,
+            Stats {
+                pushed: 7,
+                consumed: 2,
+                overwritten: 1,
+                dropped: 1,
+                overflows: 3,
+                high_water_mark: 4,
+            }
+        );
+
+        buffer.skip(2).unwrap();
+        buffer.reset_stats();
+        assert_eq!(
+            buffer.stats,
+            Stats {
+                high_water_mark: 2,
+                ..Stats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn check_wraparound() {
+        let mut buffer = RingBuffer::<u8>::new(5).unwrap();
+        assert_eq!(buffer.storage.len(), 5);
+
+        buffer.push(&[1, 2, 3, 4]).unwrap();
+        buffer.skip(3).unwrap();
+        buffer.push(&[5, 6, 7]).unwrap();
+        assert_eq!(buffer.head, 3);
+        assert_eq!(buffer.peek(2).unwrap(), &[4, 5]);
+
+        // Peeking across the end of the storage copies the elements, without moving the
+        // contents.
+        let mut out = [0; 6];
+        assert_eq!(buffer.peek_into(&mut out), 4);
+        assert_eq!(out, [4, 5, 6, 7, 0, 0]);
+        assert_eq!(buffer.head, 3);
+        assert_eq!(buffer.peek(4).unwrap(), &[4, 5, 6, 7]);
+        assert_eq!(buffer.head, 3);
+        assert_eq!(buffer.storage[..], [6, 7, 3, 4, 5]);
+
+        buffer.skip(4).unwrap();
+        assert_eq!(buffer.len, 0);
+        buffer.push(&[]).unwrap();
+        assert!(buffer.peek(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_peek_into() {
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        let mut out = [9; 2];
+        assert_eq!(buffer.peek_into(&mut out), 0);
+        assert_eq!(out, [9, 9]);
+
+        buffer.push(&[1, 2, 3]).unwrap();
+        buffer.skip(2).unwrap();
+        buffer.push(&[4, 5]).unwrap();
+        assert_eq!(buffer.storage[..], [5, 2, 3, 4]);
+
+        // Shorter than the readable elements: only the start of the wrapped contents.
+        let mut out = [0; 2];
+        assert_eq!(buffer.peek_into(&mut out), 2);
+        assert_eq!(out, [3, 4]);
+
+        // Exactly as long: both sides of the wrap.
+        let mut out = [0; 3];
+        assert_eq!(buffer.peek_into(&mut out), 3);
+        assert_eq!(out, [3, 4, 5]);
+
+        // Longer: the rest of `dst` is left alone.
+        let mut out = [0; 5];
+        assert_eq!(buffer.peek_into(&mut out), 3);
+        assert_eq!(out, [3, 4, 5, 0, 0]);
+
+        assert_eq!(buffer.peek_into(&mut []), 0);
+        assert_eq!(buffer.head, 2);
+        assert_eq!(buffer.len, 3);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn check_mirrored_peek() {
+        let mut buffer = RingBuffer::<u8>::new_mirrored(1).unwrap();
+        assert!(buffer.storage.is_mirrored());
+        let capacity = buffer.capacity();
+        assert_eq!(capacity, storage::Mirror::granularity());
+
+        let data = (0..capacity).map(|i| i as u8).collect::<Vec<_>>();
+        buffer.push(&data).unwrap();
+        buffer.skip(capacity - 2).unwrap();
+        buffer.push(&[1, 2]).unwrap();
+
+        // Peeking across the end of the storage doesn't move anything.
+        let head = buffer.head;
+        assert_eq!(
+            buffer.peek(4).unwrap(),
+            &[data[capacity - 2], data[capacity - 1], 1, 2]
+        );
+        assert_eq!(buffer.head, head);
+    }
+
+    #[test]
+    fn check_typed_elements() {
+        let mut buffer =
+            RingBuffer::<i16>::with_policy(3, OverflowPolicy::TruncateIncoming).unwrap();
+        buffer.push(&[-1, 2]).unwrap();
+        assert_eq!(
+            buffer.push(&[-3, 4]),
+            Ok(Pushed {
+                stored: 1,
+                dropped: 1
+            })
+        );
+        buffer.skip(2).unwrap();
+        buffer.push(&[i16::MAX, i16::MIN]).unwrap();
+        assert_eq!(contents(&mut buffer), &[-3, i16::MAX, i16::MIN]);
+
+        let mut buffer = RingBuffer::<f64>::new_mirrored(1).unwrap();
+        assert_eq!(
+            buffer.capacity() * std::mem::size_of::<f64>() % storage::Mirror::granularity(),
+            0
+        );
+        buffer.push(&[0.5, -1.5]).unwrap();
+        assert_eq!(buffer.peek(2).unwrap(), &[0.5, -1.5]);
+    }
+
+    #[test]
+    fn check_peek_at() {
+        let mut buffer = RingBuffer::<u8>::new(5).unwrap();
+        buffer.push(&[0, 0, 0, 1, 2]).unwrap();
+        buffer.skip(3).unwrap();
+        buffer.push(&[3, 4, 5]).unwrap();
+
+        let mut out = [0; 3];
+        buffer.peek_at(1, &mut out).unwrap();
+        assert_eq!(out, [2, 3, 4]);
+        buffer.peek_at(2, &mut out).unwrap();
+        assert_eq!(out, [3, 4, 5]);
+        assert_eq!(
+            buffer.peek_at(3, &mut out),
+            Err(Error::OutOfBounds {
+                requested: 6,
+                available: 5
+            })
+        );
+        assert!(buffer.peek_at(usize::MAX, &mut []).is_err());
+        assert_eq!(buffer.head, 3);
+
+        assert_eq!(buffer.get(0), Some(&1));
+        assert_eq!(buffer.get(3), Some(&4));
+        assert_eq!(buffer.get(5), None);
+    }
+
+    #[test]
+    fn check_as_slices() {
+        let mut buffer = RingBuffer::<u8>::new(5).unwrap();
+        assert_eq!(buffer.as_slices(0).unwrap(), (&[][..], &[][..]));
+        assert_eq!(
+            buffer.as_slices(1),
+            Err(Error::OutOfBounds {
+                requested: 1,
+                available: 0
+            })
+        );
+
+        buffer.push(&[0, 0, 0, 1, 2]).unwrap();
+        buffer.skip(3).unwrap();
+        buffer.push(&[3, 4, 5]).unwrap();
+
+        assert_eq!(buffer.as_slices(0).unwrap(), (&[][..], &[][..]));
+        assert_eq!(buffer.as_slices(1).unwrap(), (&[1][..], &[][..]));
+        assert_eq!(buffer.as_slices(2).unwrap(), (&[1, 2][..], &[][..]));
+        assert_eq!(buffer.as_slices(4).unwrap(), (&[1, 2][..], &[3, 4][..]));
+        assert_eq!(buffer.as_slices(5).unwrap(), (&[1, 2][..], &[3, 4, 5][..]));
+        assert!(buffer.as_slices(6).is_err());
+        assert_eq!(buffer.head, 3);
+    }
+
+    #[test]
+    fn check_positions() {
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        buffer.push(&[0, 1, 2]).unwrap();
+        buffer.skip(1).unwrap();
+        buffer.push(&[3, 4, 5]).unwrap();
+        assert_eq!(buffer.read_position(), 2);
+        assert_eq!(buffer.write_position(), 6);
+
+        let mut out = [0; 2];
+        buffer.peek_at_position(3, &mut out).unwrap();
+        assert_eq!(out, [3, 4]);
+        assert_eq!(
+            buffer.peek_at_position(1, &mut out),
+            Err(Error::Evicted {
+                requested: 1,
+                oldest: 2
+            })
+        );
+        assert!(buffer.peek_at_position(5, &mut out).is_err());
+        buffer.push(&[6, 7, 8, 9, 10]).unwrap();
+        assert_eq!(buffer.read_position(), 7);
+        buffer.peek_at_position(9, &mut out).unwrap();
+        assert_eq!(out, [9, 10]);
+
+        buffer.resize(2, ResizePolicy::KeepNewest).unwrap();
+        assert_eq!(buffer.read_position(), 9);
+        buffer.clear();
+        assert_eq!(buffer.read_position(), 11);
+        assert_eq!(buffer.write_position(), 11);
+    }
+
+    #[test]
+    fn check_rewind() {
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        buffer.push(&[1, 2, 3]).unwrap();
+        buffer.skip(2).unwrap();
+        assert_eq!(buffer.retained(), 0);
+        assert!(buffer.rewind(1).is_err());
+
+        buffer.set_retention(3);
+        buffer.mark("start");
+        buffer.skip(1).unwrap();
+        buffer.push(&[4, 5]).unwrap();
+        buffer.skip(1).unwrap();
+        assert_eq!(buffer.retained(), 2);
+        buffer.rewind(2).unwrap();
+        assert_eq!(contents(&mut buffer), &[3, 4, 5]);
+        assert_eq!(buffer.read_position(), 2);
+        assert_eq!(buffer.stats().consumed, 2);
+
+        // Pushing overwrites the consumed elements first.
+        buffer.skip(3).unwrap();
+        buffer.push(&[6, 7]).unwrap();
+        assert_eq!(buffer.retained(), 2);
+        assert_eq!(
+            buffer.rewind(3),
+            Err(Error::Evicted {
+                requested: 2,
+                oldest: 3
+            })
+        );
+        assert_eq!(
+            buffer.seek_to_mark("start").unwrap_err().status(),
+            Status::Evicted
+        );
+
+        buffer.rewind(2).unwrap();
+        assert_eq!(contents(&mut buffer), &[4, 5, 6, 7]);
+        buffer.mark("four");
+        buffer.skip(3).unwrap();
+        buffer.seek_to_mark("four").unwrap();
+        assert_eq!(buffer.peek(1).unwrap(), &[4]);
+        assert_eq!(buffer.remove_mark("four"), Some(3));
+        assert_eq!(buffer.seek_to_mark("four"), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn check_find() {
+        let mut buffer = RingBuffer::<u8>::new(8).unwrap();
+        buffer.push(b"xxxxxab\r").unwrap();
+        buffer.skip(5).unwrap();
+        buffer.push(b"\ncd\r\n").unwrap();
+
+        // The delimiter wraps around the end of the storage.
+        assert_eq!(buffer.find(b"\r\n"), Ok(Some(2)));
+        assert_eq!(buffer.find(b"cd\r\n"), Ok(Some(4)));
+        assert_eq!(buffer.find(b"\n\n"), Ok(None));
+        assert_eq!(buffer.find(b"too long a pattern"), Ok(None));
+        assert_eq!(buffer.head, 5);
+
+        // An empty delimiter would be found without popping anything.
+        let mut line = [0; 8];
+        assert_eq!(buffer.find(b""), Err(Error::InvalidArgument));
+        assert_eq!(
+            buffer.pop_until(b"", &mut line),
+            Err(Error::InvalidArgument)
+        );
+
+        assert_eq!(buffer.pop_until(b"\r\n", &mut line[..3]), Ok(None));
+        assert_eq!(buffer.pop_until(b"\r\n", &mut line), Ok(Some(4)));
+        assert_eq!(&line[..4], b"ab\r\n");
+        assert_eq!(buffer.pop_until(b"\r\n", &mut line), Ok(Some(4)));
+        assert_eq!(&line[..4], b"cd\r\n");
+        assert_eq!(buffer.pop_until(b"\r\n", &mut line), Ok(None));
+    }
+
+    #[test]
+    fn check_pop() {
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        buffer.push(&[1, 2, 3, 4, 5, 6]).unwrap();
+
+        let mut out = [0; 3];
+        assert_eq!(
+            buffer.pop_exact(&mut [0; 5]),
+            Err(Error::OutOfBounds {
+                requested: 5,
+                available: 4
+            })
+        );
+        buffer.pop_exact(&mut out).unwrap();
+        assert_eq!(out, [3, 4, 5]);
+
+        buffer.push(&[7]).unwrap();
+        assert_eq!(buffer.pop(&mut out), 2);
+        assert_eq!(out[..2], [6, 7]);
+        assert_eq!(buffer.pop(&mut out), 0);
+        assert_eq!(buffer.stats.consumed, 5);
+    }
+
+    #[test]
+    fn check_reserve_commit() {
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        buffer.push(&[1, 2, 3]).unwrap();
+        buffer.skip(2).unwrap();
+
+        let (first, second) = buffer.reserve(3).unwrap();
+        assert_eq!((first.len(), second.len()), (1, 2));
+        first.copy_from_slice(&[4]);
+        second.copy_from_slice(&[5, 6]);
+        buffer.commit(2).unwrap();
+        assert_eq!(contents(&mut buffer), &[3, 4, 5]);
+        assert_eq!(
+            buffer.commit(2),
+            Err(Error::OutOfBounds {
+                requested: 2,
+                available: 1
+            })
+        );
+
+        // Overwriting makes room right away.
+        let (first, second) = buffer.reserve(3).unwrap();
+        assert_eq!(first.len() + second.len(), 3);
+        buffer.commit(0).unwrap();
+        assert_eq!(contents(&mut buffer), &[5]);
+        assert_eq!(buffer.stats.overwritten, 2);
+
+        buffer.set_policy(OverflowPolicy::TruncateIncoming);
+        buffer.push(&[7, 8]).unwrap();
+        let (first, second) = buffer.reserve(5).unwrap();
+        assert_eq!(first.len() + second.len(), 1);
+        assert_eq!(buffer.stats.dropped, 4);
+
+        buffer.set_policy(OverflowPolicy::Reject);
+        assert!(buffer.reserve(2).is_err());
+        // Committing without reserving overwrites retained elements too.
+        let mut buffer = RingBuffer::new(4).unwrap();
+        buffer.set_retention(4);
+        buffer.push(&[1, 2, 3, 4]).unwrap();
+        buffer.skip(4).unwrap();
+        buffer.commit(2).unwrap();
+        assert_eq!(buffer.retained(), 2);
+        assert!(buffer.rewind(4).is_err());
+        buffer.rewind(2).unwrap();
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.peek(4).unwrap(), &[3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn check_resize() {
+        let mut buffer = RingBuffer::<u8>::new(4).unwrap();
+        buffer.push(&[1, 2, 3, 4, 5]).unwrap();
+
+        assert_eq!(buffer.resize(6, ResizePolicy::KeepNewest), Ok(0));
+        assert_eq!(buffer.capacity(), 6);
+        buffer.push(&[6, 7]).unwrap();
+        assert_eq!(contents(&mut buffer), &[2, 3, 4, 5, 6, 7]);
+
+        assert_eq!(buffer.resize(4, ResizePolicy::KeepNewest), Ok(2));
+        assert_eq!(contents(&mut buffer), &[4, 5, 6, 7]);
+        assert_eq!(buffer.resize(2, ResizePolicy::KeepOldest), Ok(2));
+        assert_eq!(buffer.write_position(), 5);
+        assert_eq!(contents(&mut buffer), &[4, 5]);
+
+        // The counters still add up.
+        let stats = buffer.stats();
+        assert_eq!(stats.pushed, 4);
+        assert_eq!(stats.overwritten, 2);
+        assert_eq!(stats.dropped, 3);
+        assert_eq!(
+            stats.pushed,
+            stats.consumed + stats.overwritten + buffer.len() as u64
+        );
+
+        buffer.reset_stats();
+        assert_eq!(buffer.resize(1, ResizePolicy::KeepOldest), Ok(1));
+        assert_eq!(buffer.stats().pushed, 0);
+    }
+
+    #[test]
+    fn check_safe_api() {
+        let mut buffer = RingBuffer::<u8>::new(3).unwrap();
+        assert!(buffer.is_empty());
+        buffer.extend(b"abcd");
+        assert!(buffer.is_full());
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(
+            format!("{:?}", buffer),
+            "RingBuffer { capacity: 3, policy: OverwriteOldest, contents: [98, 99, 100] }"
+        );
+
+        let mut clone = buffer.clone();
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.stats().consumed, 3);
+        assert_eq!(clone.peek(3).unwrap(), b"bcd");
+
+        clone.set_policy(OverflowPolicy::Reject);
+        clone.skip(1).unwrap();
+        clone.extend(vec![b'e', b'f']);
+        assert_eq!(clone.peek(3).unwrap(), b"cde");
+
+        let mut collected = (1..=4).collect::<RingBuffer<i32>>();
+        assert_eq!(collected.capacity(), 4);
+        assert_eq!(collected.peek(4).unwrap(), &[1, 2, 3, 4]);
     }
 }
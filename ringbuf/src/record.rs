@@ -0,0 +1,324 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A ring buffer of whole records, which are never split or partially overwritten.
+//!
+//! Each record is stored as a little-endian `u32` length followed by its bytes.
+use crate::ffi::{slice, status};
+use crate::{Error, OverflowPolicy, Pushed, RingBuffer, Status};
+use std::convert::TryFrom;
+use std::mem;
+
+const HEADER: usize = mem::size_of::<u32>();
+
+/// A fixed-capacity queue of byte records.
+pub struct RecordRing {
+    buffer: RingBuffer<u8>,
+    // Number of records in `buffer`.
+    records: usize,
+    policy: OverflowPolicy,
+}
+
+impl RecordRing {
+    /// Creates an empty ring of the specified capacity in bytes, which includes 4 bytes of
+    /// overhead per record.
+    ///
+    /// With `TruncateIncoming`, records that don't fit are dropped just like with
+    /// `DropIncoming`, as they cannot be split.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Result<Self, Error> {
+        Ok(RecordRing {
+            buffer: RingBuffer::with_policy(capacity, OverflowPolicy::Reject)?,
+            records: 0,
+            policy,
+        })
+    }
+
+    /// How many bytes the ring can hold, including the overhead of each record.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// How many records can be read from the ring.
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    /// The `OverflowPolicy` used when pushing records that don't fit.
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Pushes a whole record, returning how many records were stored (0 or 1) and how many
+    /// were dropped, either old ones that got evicted or the new one.
+    ///
+    /// Fails with `Full` if the record is larger than the capacity, or if it doesn't fit and
+    /// the policy is `Reject`. Fails with `InvalidArgument` if it is longer than `u32::MAX`.
+    pub fn push_record(&mut self, record: &[u8]) -> Result<Pushed, Error> {
+        let len = u32::try_from(record.len()).map_err(|_| Error::InvalidArgument)?;
+        let size = HEADER + record.len();
+        let leeway = self.capacity() - self.buffer.len();
+        if size > self.capacity() {
+            return Err(Error::Full {
+                requested: size,
+                available: leeway,
+            });
+        }
+
+        let mut evicted = 0;
+        if size > leeway {
+            match self.policy {
+                OverflowPolicy::OverwriteOldest => {
+                    while self.capacity() - self.buffer.len() < size {
+                        self.skip_record()?;
+                        evicted += 1;
+                    }
+                }
+                OverflowPolicy::Reject => {
+                    return Err(Error::Full {
+                        requested: size,
+                        available: leeway,
+                    })
+                }
+                OverflowPolicy::TruncateIncoming | OverflowPolicy::DropIncoming => {
+                    return Ok(Pushed {
+                        stored: 0,
+                        dropped: 1,
+                    })
+                }
+            }
+        }
+
+        self.buffer.push(&len.to_le_bytes())?;
+        self.buffer.push(record)?;
+        self.records += 1;
+        Ok(Pushed {
+            stored: 1,
+            dropped: evicted,
+        })
+    }
+
+    /// The length of the oldest record, if there is one.
+    pub fn record_len(&self) -> Option<usize> {
+        let mut header = [0; HEADER];
+        self.buffer.peek_at(0, &mut header).ok()?;
+        Some(u32::from_le_bytes(header) as usize)
+    }
+
+    fn check_record(&self) -> Result<usize, Error> {
+        self.record_len().ok_or(Error::OutOfBounds {
+            requested: 1,
+            available: 0,
+        })
+    }
+
+    /// Returns the oldest record, without consuming it.
+    ///
+    /// Fails with `OutOfBounds` if the ring is empty.
+    pub fn peek_record(&mut self) -> Result<&[u8], Error> {
+        let len = self.check_record()?;
+        Ok(&self.buffer.peek(HEADER + len)?[HEADER..])
+    }
+
+    /// Consumes the oldest record, and returns its length.
+    ///
+    /// Fails with `OutOfBounds` if the ring is empty.
+    pub fn skip_record(&mut self) -> Result<usize, Error> {
+        let len = self.check_record()?;
+        self.buffer.skip(HEADER + len)?;
+        self.records -= 1;
+        Ok(len)
+    }
+
+    /// Consumes every record.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.records = 0;
+    }
+}
+
+/// Creates a new record ring of the specified capacity in bytes and `OverflowPolicy`, and
+/// stores it in `out`.
+///
+/// Fails with `InvalidArgument` if `policy` is not one of the `OverflowPolicy` values.
+///
+/// It is undefined behaviour to pass an invalid pointer to `out`.
+#[no_mangle]
+pub extern "C" fn record_new(capacity: usize, policy: u32, out: *mut *mut RecordRing) -> Status {
+    status(|| {
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        let policy = OverflowPolicy::try_from(policy)?;
+        *out = Box::into_raw(Box::new(RecordRing::new(capacity, policy)?));
+        Ok(())
+    })
+}
+
+/// How many records can be read from the ring?
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RecordRing`.
+#[no_mangle]
+pub extern "C" fn record_count(ring: *const RecordRing) -> usize {
+    let ring = unsafe { &*ring };
+    ring.len()
+}
+
+/// Pushes a whole record of `n` bytes, storing how many records were stored and dropped in
+/// `out` (unless it's null).
+///
+/// Fails with `Full` if the record is larger than the capacity, or if it doesn't fit and the
+/// policy is `Reject`.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RecordRing`,
+/// to pass an invalid pointer to bytes which is not of the matching length, or to pass an
+/// invalid non-null pointer to `out`.
+#[no_mangle]
+pub extern "C" fn record_push(
+    ring: *mut RecordRing,
+    bytes: *const u8,
+    n: usize,
+    out: *mut Pushed,
+) -> Status {
+    status(|| {
+        let ring = unsafe { ring.as_mut() }.ok_or(Error::NullPointer)?;
+        let pushed = ring.push_record(slice(bytes, n)?)?;
+        if let Some(out) = unsafe { out.as_mut() } {
+            *out = pushed;
+        }
+        Ok(())
+    })
+}
+
+/// Peeks the oldest record, storing a pointer to its bytes in `out` and its length in
+/// `out_len`.
+///
+/// Fails with `OutOfBounds` if the ring is empty.
+///
+/// The results should **not** be read from after pushing or deleting the ring.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RecordRing`,
+/// or to pass invalid pointers to `out` or `out_len`.
+#[no_mangle]
+pub extern "C" fn record_peek(
+    ring: *mut RecordRing,
+    out: *mut *const u8,
+    out_len: *mut usize,
+) -> Status {
+    status(|| {
+        let ring = unsafe { ring.as_mut() }.ok_or(Error::NullPointer)?;
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        let out_len = unsafe { out_len.as_mut() }.ok_or(Error::NullPointer)?;
+        let record = ring.peek_record()?;
+        *out = record.as_ptr();
+        *out_len = record.len();
+        Ok(())
+    })
+}
+
+/// Skips the oldest record, storing its length in `out` (unless it's null).
+///
+/// Fails with `OutOfBounds` if the ring is empty.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RecordRing`,
+/// or to pass an invalid non-null pointer to `out`.
+#[no_mangle]
+pub extern "C" fn record_skip(ring: *mut RecordRing, out: *mut usize) -> Status {
+    status(|| {
+        let ring = unsafe { ring.as_mut() }.ok_or(Error::NullPointer)?;
+        let len = ring.skip_record()?;
+        if let Some(out) = unsafe { out.as_mut() } {
+            *out = len;
+        }
+        Ok(())
+    })
+}
+
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `RecordRing`.
+#[no_mangle]
+pub extern "C" fn record_del(ring: *mut RecordRing) -> Status {
+    status(|| {
+        if ring.is_null() {
+            return Err(Error::NullPointer);
+        }
+        drop(unsafe { Box::from_raw(ring) });
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_whole_records() {
+        let mut ring = RecordRing::new(16, OverflowPolicy::OverwriteOldest).unwrap();
+        ring.push_record(b"one").unwrap();
+        ring.push_record(b"").unwrap();
+        assert_eq!(
+            ring.push_record(b"two"),
+            Ok(Pushed {
+                stored: 1,
+                dropped: 1
+            })
+        );
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.peek_record().unwrap(), b"");
+        assert_eq!(ring.skip_record(), Ok(0));
+
+        // Wraps around the end of the storage, and then evicts the oldest record whole.
+        assert_eq!(
+            ring.push_record(b"three"),
+            Ok(Pushed {
+                stored: 1,
+                dropped: 0
+            })
+        );
+        assert_eq!(
+            ring.push_record(b"fo"),
+            Ok(Pushed {
+                stored: 1,
+                dropped: 1
+            })
+        );
+        assert_eq!(ring.peek_record().unwrap(), b"three");
+        assert_eq!(ring.skip_record(), Ok(5));
+        assert_eq!(ring.peek_record().unwrap(), b"fo");
+        ring.skip_record().unwrap();
+        assert!(ring.is_empty());
+        assert!(ring.peek_record().is_err());
+
+        assert!(ring.push_record(&[0; 13]).is_err());
+    }
+
+    #[test]
+    fn check_record_policies() {
+        let mut ring = RecordRing::new(10, OverflowPolicy::Reject).unwrap();
+        ring.push_record(b"abc").unwrap();
+        assert_eq!(
+            ring.push_record(b"def"),
+            Err(Error::Full {
+                requested: 7,
+                available: 3
+            })
+        );
+
+        let mut ring = RecordRing::new(10, OverflowPolicy::TruncateIncoming).unwrap();
+        ring.push_record(b"abc").unwrap();
+        assert_eq!(
+            ring.push_record(b"def"),
+            Ok(Pushed {
+                stored: 0,
+                dropped: 1
+            })
+        );
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring.record_len(), Some(3));
+    }
+}
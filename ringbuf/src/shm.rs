@@ -0,0 +1,481 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A single-producer single-consumer ring buffer in named shared memory, so that the producer
+//! and the consumer can live in different processes.
+//!
+//! The segment is a file in `/dev/shm` (which is what `shm_open` uses on Linux) starting
+//! with a fixed `Header`, followed by the bytes. Both ends coordinate through the same
+//! wait-free algorithm as the in-process `spsc` ring, on 64-bit atomics which live in the
+//! segment itself.
+use crate::ffi::{slice, slice_mut, status, string};
+use crate::spsc::Ring;
+use crate::storage::Mapping;
+use crate::{Error, OverflowPolicy, Pushed, Stats, Status};
+use std::convert::TryFrom;
+use std::fs::{self, OpenOptions};
+use std::mem;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// Identifies a ring buffer segment, stored last when creating one so that a segment which is
+/// still being initialized is never mistaken for a valid one.
+const MAGIC: u64 = u64::from_le_bytes(*b"RINGBUF\0");
+
+/// Version of the `Header` layout, bumped on any incompatible change.
+const VERSION: u32 = 1;
+
+/// The start of a segment, followed by `capacity` bytes of data.
+#[repr(C)]
+struct Header {
+    magic: AtomicU64,
+    version: u32,
+    policy: u32,
+    capacity: u64,
+    head: AtomicU64,
+    tail: AtomicU64,
+    oldest: AtomicU64,
+    // The same counters as `Stats`, all updated by the producer except `consumed`.
+    pushed: AtomicU64,
+    consumed: AtomicU64,
+    overwritten: AtomicU64,
+    dropped: AtomicU64,
+    overflows: AtomicU64,
+    high_water_mark: AtomicU64,
+}
+
+/// One process' view of a ring buffer in shared memory.
+///
+/// At any time, exactly one process (and thread) may push, and exactly one may peek and skip.
+pub struct SharedRing {
+    mapping: Mapping,
+    policy: OverflowPolicy,
+}
+
+/// Path of the file backing the segment called `name`, which may not contain slashes.
+fn path(name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(PathBuf::from("/dev/shm").join(name))
+}
+
+impl SharedRing {
+    /// Creates a new segment called `name`, holding a buffer of the specified capacity and
+    /// `OverflowPolicy`.
+    ///
+    /// Fails with `Io` if a segment with the same name already exists.
+    pub fn create(name: &str, capacity: usize, policy: OverflowPolicy) -> Result<Self, Error> {
+        let path = path(name)?;
+        let len = capacity
+            .checked_add(mem::size_of::<Header>())
+            .ok_or(Error::AllocationFailed)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        let init = || -> Result<Self, Error> {
+            file.set_len(len as u64)?;
+            let mapping = Mapping::new(&file, len)?;
+
+            // The file starts zeroed, so only the constant fields need to be written.
+            let header = mapping.as_ptr().cast::<Header>();
+            unsafe {
+                (*header).version = VERSION;
+                (*header).policy = policy as u32;
+                (*header).capacity = capacity as u64;
+                (*header).magic.store(MAGIC, Ordering::Release);
+            }
+            Ok(SharedRing { mapping, policy })
+        };
+        init().map_err(|error| {
+            let _ = fs::remove_file(&path);
+            error
+        })
+    }
+
+    /// Attaches to the existing segment called `name`.
+    ///
+    /// Fails with `Io` if there is no such segment, or with `InvalidArgument` if it is not a
+    /// ring buffer of this version, or is still being created.
+    pub fn open(name: &str) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path(name)?)?;
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| Error::InvalidArgument)?;
+        if len < mem::size_of::<Header>() {
+            return Err(Error::InvalidArgument);
+        }
+        let mapping = Mapping::new(&file, len)?;
+
+        let header = unsafe { &*mapping.as_ptr().cast::<Header>() };
+        if header.magic.load(Ordering::Acquire) != MAGIC
+            || header.version != VERSION
+            || header.capacity != (len - mem::size_of::<Header>()) as u64
+        {
+            return Err(Error::InvalidArgument);
+        }
+        let policy = OverflowPolicy::try_from(header.policy)?;
+        Ok(SharedRing { mapping, policy })
+    }
+
+    /// Removes the segment called `name`. Processes attached to it keep using it until they
+    /// drop their `SharedRing`.
+    pub fn unlink(name: &str) -> Result<(), Error> {
+        fs::remove_file(path(name)?)?;
+        Ok(())
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*self.mapping.as_ptr().cast::<Header>() }
+    }
+
+    fn ring(&self) -> Ring<'_> {
+        let header = self.header();
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                self.mapping
+                    .as_ptr()
+                    .add(mem::size_of::<Header>())
+                    .cast::<AtomicU8>(),
+                self.capacity(),
+            )
+        };
+        Ring {
+            head: &header.head,
+            tail: &header.tail,
+            oldest: &header.oldest,
+            data,
+            policy: self.policy,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mapping.len() - mem::size_of::<Header>()
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Returns the counters shared by both processes.
+    pub fn stats(&self) -> Stats {
+        let header = self.header();
+        Stats {
+            pushed: header.pushed.load(Ordering::Relaxed),
+            consumed: header.consumed.load(Ordering::Relaxed),
+            overwritten: header.overwritten.load(Ordering::Relaxed),
+            dropped: header.dropped.load(Ordering::Relaxed),
+            overflows: header.overflows.load(Ordering::Relaxed),
+            high_water_mark: header.high_water_mark.load(Ordering::Relaxed),
+        }
+    }
+
+    /// How many bytes can be pushed without overflowing? Only for the producer.
+    pub fn write_available(&self) -> usize {
+        self.ring().write_available()
+    }
+
+    /// Pushes data following the `OverflowPolicy`, just like `RingBuffer::push`. Only for
+    /// the producer.
+    ///
+    /// This never waits for the consumer.
+    pub fn push(&self, bytes: &[u8]) -> Result<Pushed, Error> {
+        let header = self.header();
+        let ring = self.ring();
+        let pushed = match ring.push(bytes) {
+            Ok(pushed) => pushed,
+            Err(error) => {
+                header.overflows.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            }
+        };
+
+        let dropped = bytes.len() - pushed.stored;
+        header
+            .pushed
+            .fetch_add(pushed.stored as u64, Ordering::Relaxed);
+        header.dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+        header
+            .overwritten
+            .fetch_add((pushed.dropped - dropped) as u64, Ordering::Relaxed);
+        if pushed.dropped > 0 {
+            header.overflows.fetch_add(1, Ordering::Relaxed);
+        }
+        header
+            .high_water_mark
+            .fetch_max(ring.read_available() as u64, Ordering::Relaxed);
+        Ok(pushed)
+    }
+
+    /// How many bytes can be read? Only for the consumer.
+    pub fn read_available(&self) -> usize {
+        self.ring().read_available()
+    }
+
+    /// Copies the oldest `dst.len()` bytes into `dst`, without consuming them. Only for the
+    /// consumer.
+    ///
+    /// Fails with `OutOfBounds` if one tries to read more than available, or with `Evicted`
+    /// if the producer overwrote bytes which were not consumed yet since the last `peek` or
+    /// `skip`. Reading then resumes from the oldest byte left.
+    pub fn peek(&self, dst: &mut [u8]) -> Result<(), Error> {
+        self.ring().peek(dst)
+    }
+
+    /// Consumes the oldest `n` bytes. Only for the consumer.
+    ///
+    /// Fails just like `peek`, without consuming anything.
+    pub fn skip(&self, n: usize) -> Result<(), Error> {
+        self.ring().skip(n)?;
+        self.header()
+            .consumed
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Creates a shared memory segment called `name` holding a ring buffer of the specified
+/// capacity and `OverflowPolicy`, and stores this process' view of it in `out`.
+///
+/// At any time, exactly one process may push to the buffer, and exactly one may peek and skip.
+/// None of the `shm_ring_` functions ever block.
+///
+/// Fails with `Io` if a segment with the same name already exists, or with `InvalidArgument`
+/// if `name` contains a slash or `policy` is not one of the `OverflowPolicy` values.
+///
+/// It is undefined behaviour to pass an invalid pointer to `name` or `out`.
+#[no_mangle]
+pub extern "C" fn shm_ring_create(
+    name: *const c_char,
+    capacity: usize,
+    policy: u32,
+    out: *mut *mut SharedRing,
+) -> Status {
+    status(|| {
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        let ring = SharedRing::create(string(name)?, capacity, OverflowPolicy::try_from(policy)?)?;
+        *out = Box::into_raw(Box::new(ring));
+        Ok(())
+    })
+}
+
+/// Attaches to the existing shared memory segment called `name`, and stores this process'
+/// view of it in `out`.
+///
+/// Fails with `Io` if there is no such segment, or with `InvalidArgument` if it is not a ring
+/// buffer of this version, or is still being created.
+///
+/// It is undefined behaviour to pass an invalid pointer to `name` or `out`.
+#[no_mangle]
+pub extern "C" fn shm_ring_open(name: *const c_char, out: *mut *mut SharedRing) -> Status {
+    status(|| {
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        *out = Box::into_raw(Box::new(SharedRing::open(string(name)?)?));
+        Ok(())
+    })
+}
+
+/// Removes the shared memory segment called `name`. Processes attached to it keep using it
+/// until they close it.
+///
+/// It is undefined behaviour to pass an invalid pointer to `name`.
+#[no_mangle]
+pub extern "C" fn shm_ring_unlink(name: *const c_char) -> Status {
+    status(|| SharedRing::unlink(string(name)?))
+}
+
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`.
+#[no_mangle]
+pub extern "C" fn shm_ring_capacity(ring: *const SharedRing) -> usize {
+    let ring = unsafe { &*ring };
+    ring.capacity()
+}
+
+/// Stores the counters shared by both processes in `out`.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`,
+/// or to pass an invalid pointer to `out`.
+#[no_mangle]
+pub extern "C" fn shm_ring_stats(ring: *const SharedRing, out: *mut Stats) -> Status {
+    status(|| {
+        let ring = unsafe { ring.as_ref() }.ok_or(Error::NullPointer)?;
+        let out = unsafe { out.as_mut() }.ok_or(Error::NullPointer)?;
+        *out = ring.stats();
+        Ok(())
+    })
+}
+
+/// How much data can be pushed without overflowing?
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`.
+#[no_mangle]
+pub extern "C" fn shm_ring_write_available(ring: *const SharedRing) -> usize {
+    let ring = unsafe { &*ring };
+    ring.write_available()
+}
+
+/// Pushes data to the buffer, storing how many bytes were stored and dropped in `out`
+/// (unless it's null).
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`,
+/// to pass an invalid pointer to bytes which is not of the matching length, or to pass an
+/// invalid non-null pointer to `out`.
+#[no_mangle]
+pub extern "C" fn shm_ring_push(
+    ring: *const SharedRing,
+    bytes: *const u8,
+    n: usize,
+    out: *mut Pushed,
+) -> Status {
+    status(|| {
+        let ring = unsafe { ring.as_ref() }.ok_or(Error::NullPointer)?;
+        let pushed = ring.push(slice(bytes, n)?)?;
+        if let Some(out) = unsafe { out.as_mut() } {
+            *out = pushed;
+        }
+        Ok(())
+    })
+}
+
+/// How much data can be read from the buffer?
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`.
+#[no_mangle]
+pub extern "C" fn shm_ring_read_available(ring: *const SharedRing) -> usize {
+    let ring = unsafe { &*ring };
+    ring.read_available()
+}
+
+/// Peeks from the buffer, copying the oldest `n` bytes into `dst`.
+///
+/// Fails with `OutOfBounds` if one tries to read more than available in the buffer, or with
+/// `Evicted` if the producer overwrote bytes which were not consumed yet since the last peek
+/// or skip. Reading then resumes from the oldest byte left.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`,
+/// or to pass an invalid pointer to `dst` which is not of the matching length.
+#[no_mangle]
+pub extern "C" fn shm_ring_peek(ring: *const SharedRing, dst: *mut u8, n: usize) -> Status {
+    status(|| {
+        let ring = unsafe { ring.as_ref() }.ok_or(Error::NullPointer)?;
+        ring.peek(slice_mut(dst, n)?)
+    })
+}
+
+/// Skips data from the buffer.
+///
+/// Fails just like `shm_ring_peek`, without skipping anything.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`.
+#[no_mangle]
+pub extern "C" fn shm_ring_skip(ring: *const SharedRing, n: usize) -> Status {
+    status(|| {
+        let ring = unsafe { ring.as_ref() }.ok_or(Error::NullPointer)?;
+        ring.skip(n)
+    })
+}
+
+/// Detaches from the segment, without removing it.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-closed `SharedRing`.
+#[no_mangle]
+pub extern "C" fn shm_ring_close(ring: *mut SharedRing) -> Status {
+    status(|| {
+        if ring.is_null() {
+            return Err(Error::NullPointer);
+        }
+        drop(unsafe { Box::from_raw(ring) });
+        Ok(())
+    })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::process;
+    use std::thread;
+
+    fn name(test: &str) -> String {
+        format!("ringbuf-test-{}-{}", test, process::id())
+    }
+
+    #[test]
+    fn check_attach() {
+        let name = name("attach");
+        let producer = SharedRing::create(&name, 8, OverflowPolicy::Reject).unwrap();
+        assert!(matches!(
+            SharedRing::create(&name, 8, OverflowPolicy::Reject),
+            Err(Error::Io(_))
+        ));
+        let consumer = SharedRing::open(&name).unwrap();
+        assert_eq!(consumer.capacity(), 8);
+        assert_eq!(consumer.policy(), OverflowPolicy::Reject);
+
+        producer.push(b"abcdef").unwrap();
+        assert!(producer.push(b"ghi").is_err());
+        let mut bytes = [0; 6];
+        consumer.peek(&mut bytes).unwrap();
+        consumer.skip(6).unwrap();
+        assert_eq!(&bytes, b"abcdef");
+        assert_eq!(producer.stats().consumed, 6);
+        assert_eq!(consumer.stats().overflows, 1);
+
+        // Segments of another version, or that aren't ring buffers, are refused.
+        let file = path(&name).unwrap();
+        let mut header = fs::read(&file).unwrap();
+        header[8] += 1;
+        fs::write(&file, &header).unwrap();
+        assert!(matches!(
+            SharedRing::open(&name),
+            Err(Error::InvalidArgument)
+        ));
+        fs::write(&file, b"not a ring buffer").unwrap();
+        assert!(matches!(
+            SharedRing::open(&name),
+            Err(Error::InvalidArgument)
+        ));
+
+        SharedRing::unlink(&name).unwrap();
+        assert!(matches!(SharedRing::open(&name), Err(Error::Io(_))));
+        assert_eq!(path("a/b").err(), Some(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn check_separate_mappings() {
+        let name = name("mappings");
+        let producer = SharedRing::create(&name, 16, OverflowPolicy::Reject).unwrap();
+        let consumer = SharedRing::open(&name).unwrap();
+        SharedRing::unlink(&name).unwrap();
+        let total = 10_000u32;
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..total {
+                    while producer.push(&i.to_le_bytes()).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            });
+
+            for i in 0..total {
+                let mut bytes = [0; 4];
+                while consumer.peek(&mut bytes).is_err() {
+                    thread::yield_now();
+                }
+                consumer.skip(4).unwrap();
+                assert_eq!(u32::from_le_bytes(bytes), i);
+            }
+        });
+        assert_eq!(consumer.stats().pushed, 4 * total as u64);
+    }
+}
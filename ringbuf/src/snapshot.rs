@@ -0,0 +1,298 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A portable byte format for the state of a buffer, to move it between processes or machines.
+//!
+//! All integers are little-endian:
+//!
+//! | Offset | Field                                                    |
+//! |--------|----------------------------------------------------------|
+//! | 0      | magic, `b"RBUF"`                                         |
+//! | 4      | version, `u16`                                           |
+//! | 6      | `Element::TAG` of the element type, `u8`                 |
+//! | 7      | flags, `u8`: 1 if mirrored                               |
+//! | 8      | `OverflowPolicy`, `u32`                                  |
+//! | 12     | reserved, `u32`                                          |
+//! | 16     | capacity, `u64`                                          |
+//! | 24     | read position, `u64`                                     |
+//! | 32     | retention, `u64`                                         |
+//! | 40     | the six `Stats` counters, in order, `u64` each           |
+//! | 88     | number of readable elements, `u64`                       |
+//! | 96     | the readable elements from oldest to newest              |
+use crate::storage::Storage;
+use crate::{Error, OverflowPolicy, RingBuffer, Stats};
+use std::convert::TryFrom;
+use std::mem;
+
+const MAGIC: &[u8; 4] = b"RBUF";
+
+/// Version of the format, bumped on any incompatible change.
+const VERSION: u16 = 1;
+
+/// Length of the fixed part of the format, before the elements.
+const HEADER_LEN: usize = 96;
+
+const MIRRORED: u8 = 1;
+
+/// Largest storage, in bytes, a buffer is deserialized into. The capacity comes from the
+/// bytes being deserialized, which may not be trusted, and is allocated right away.
+const MAX_STORAGE: usize = 1 << 30;
+
+mod private {
+    /// Keeps `Element` from being implemented outside of this crate, as the tags must be
+    /// unique and the format only describes these types.
+    pub trait Sealed {}
+}
+
+/// Types of elements which can be serialized.
+pub trait Element: Copy + Default + private::Sealed {
+    /// Identifies the type in serialized buffers, so they are only deserialized as the same
+    /// type.
+    const TAG: u8;
+
+    fn write_le(self, out: &mut Vec<u8>);
+
+    /// Reads an element from exactly `size_of::<Self>()` bytes.
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! elements {
+    ($($t:ty => $tag:literal),*) => {
+        $(
+            impl private::Sealed for $t {}
+
+            impl Element for $t {
+                const TAG: u8 = $tag;
+
+                fn write_le(self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    let mut le = [0; mem::size_of::<$t>()];
+                    le.copy_from_slice(bytes);
+                    <$t>::from_le_bytes(le)
+                }
+            }
+        )*
+    };
+}
+
+elements!(
+    u8 => 0, i8 => 1, u16 => 2, i16 => 3, u32 => 4, i32 => 5, u64 => 6, i64 => 7,
+    f32 => 8, f64 => 9
+);
+
+/// Reads the fields of a serialized buffer in order.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn take(&mut self, n: usize) -> &[u8] {
+        let (field, rest) = self.0.split_at(n);
+        self.0 = rest;
+        field
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::read_le(self.take(8))
+    }
+
+    fn usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.u64()).map_err(|_| Error::InvalidArgument)
+    }
+}
+
+impl<T: Element> RingBuffer<T> {
+    /// How long is the serialized buffer?
+    pub fn serialized_len(&self) -> usize {
+        HEADER_LEN + self.len * mem::size_of::<T>()
+    }
+
+    /// Serializes the capacity, policy, read position, retention, stats and readable
+    /// elements of the buffer, so that `deserialize` can restore them anywhere.
+    ///
+    /// Consumed elements kept for `rewind`, and the marks, are not included.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.serialized_len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.push(T::TAG);
+        out.push(if self.is_mirrored() { MIRRORED } else { 0 });
+        out.extend_from_slice(&(self.policy as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+
+        let stats = self.stats;
+        for field in &[
+            self.capacity() as u64,
+            self.position,
+            self.retention as u64,
+            stats.pushed,
+            stats.consumed,
+            stats.overwritten,
+            stats.dropped,
+            stats.overflows,
+            stats.high_water_mark,
+            self.len as u64,
+        ] {
+            field.write_le(&mut out);
+        }
+
+        let (first, second) = self.slices(0, self.len);
+        for element in first.iter().chain(second) {
+            element.write_le(&mut out);
+        }
+        out
+    }
+
+    /// Restores a buffer serialized by `serialize`, with the same element type.
+    ///
+    /// Mirrored buffers are restored as regular ones if their capacity is not a multiple of
+    /// the page size here.
+    ///
+    /// Fails with `InvalidArgument` if the bytes are not a serialized buffer of this version
+    /// and element type, if its capacity takes more than 1 GiB, or if its read position and
+    /// stats do not add up, or with `AllocationFailed` if the memory cannot be allocated.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN
+            || &bytes[..4] != MAGIC
+            || bytes[4..6] != VERSION.to_le_bytes()
+            || bytes[6] != T::TAG
+        {
+            return Err(Error::InvalidArgument);
+        }
+        let mirrored = bytes[7] & MIRRORED != 0;
+        let mut fields = Fields(&bytes[8..]);
+        let policy = OverflowPolicy::try_from(u32::read_le(fields.take(4)))?;
+        fields.take(4);
+        let capacity = fields.usize()?;
+        let position = fields.u64();
+        let retention = fields.usize()?;
+        let stats = Stats {
+            pushed: fields.u64(),
+            consumed: fields.u64(),
+            overwritten: fields.u64(),
+            dropped: fields.u64(),
+            overflows: fields.u64(),
+            high_water_mark: fields.u64(),
+        };
+        let len = fields.usize()?;
+        let size = mem::size_of::<T>();
+        if len > capacity
+            || capacity.saturating_mul(size) > MAX_STORAGE
+            || Some(fields.0.len()) != len.checked_mul(size)
+        {
+            return Err(Error::InvalidArgument);
+        }
+        // Every consumed or overwritten element moved the read position forward, and the
+        // positions must not overflow while pushing.
+        let behind = stats.consumed.checked_add(stats.overwritten);
+        if behind.map_or(true, |behind| behind > position)
+            || position.checked_add(capacity as u64).is_none()
+            || stats.high_water_mark < len as u64
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Mirrored storage would round the capacity up, to a multiple of the page size of
+        // this machine, so the buffer is restored on the heap when that would change it.
+        let storage = if mirrored && capacity % Storage::<T>::mirror_granularity() == 0 {
+            Self::mirrored_storage(capacity)?
+        } else {
+            Storage::heap(capacity)?
+        };
+        let mut buffer = Self::with_storage(storage);
+        for (element, bytes) in buffer.storage.iter_mut().zip(fields.0.chunks_exact(size)) {
+            *element = T::read_le(bytes);
+        }
+        buffer.len = len;
+        buffer.position = position;
+        buffer.set_retention(retention);
+        buffer.policy = policy;
+        buffer.stats = stats;
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_round_trip() {
+        let mut buffer = RingBuffer::with_policy(4, OverflowPolicy::TruncateIncoming).unwrap();
+        buffer.push(&[1i16, -2, 3]).unwrap();
+        buffer.skip(2).unwrap();
+        buffer.push(&[4, 5, 6]).unwrap();
+        buffer.set_retention(8);
+
+        let bytes = buffer.serialize();
+        assert_eq!(bytes.len(), buffer.serialized_len());
+        let restored = RingBuffer::<i16>::deserialize(&bytes).unwrap();
+        assert_eq!(restored.capacity(), 4);
+        assert_eq!(restored.policy(), OverflowPolicy::TruncateIncoming);
+        assert_eq!(restored.read_position(), 2);
+        assert_eq!(restored.retention(), 8);
+        assert_eq!(restored.stats(), buffer.stats());
+        let mut contents = [0; 4];
+        assert_eq!(restored.peek_into(&mut contents), 4);
+        assert_eq!(contents, [3, 4, 5, 6]);
+        assert_eq!(restored.serialize(), bytes);
+
+        // Other element types, versions and truncated bytes are refused.
+        assert_eq!(
+            RingBuffer::<u16>::deserialize(&bytes).err(),
+            Some(Error::InvalidArgument)
+        );
+        let mut other = bytes.clone();
+        other[4] += 1;
+        assert_eq!(
+            RingBuffer::<i16>::deserialize(&other).err(),
+            Some(Error::InvalidArgument)
+        );
+        assert_eq!(
+            RingBuffer::<i16>::deserialize(&bytes[..bytes.len() - 1]).err(),
+            Some(Error::InvalidArgument)
+        );
+
+        // Inconsistent positions and stats are refused.
+        for (offset, value) in &[(24, 0), (24, u64::MAX), (48, 3), (80, 2)] {
+            let mut other = bytes.clone();
+            other[*offset..*offset + 8].copy_from_slice(&value.to_le_bytes());
+            assert_eq!(
+                RingBuffer::<i16>::deserialize(&other).err(),
+                Some(Error::InvalidArgument)
+            );
+        }
+
+        // Mirrored buffers keep their capacity, even if it is not a multiple of the page size.
+        let mut mirrored = RingBuffer::<u8>::new_mirrored(1).unwrap();
+        mirrored.push(b"abc").unwrap();
+        let bytes = mirrored.serialize();
+        let mut restored = RingBuffer::<u8>::deserialize(&bytes).unwrap();
+        assert_eq!(restored.capacity(), mirrored.capacity());
+        assert_eq!(restored.is_mirrored(), mirrored.is_mirrored());
+        assert_eq!(restored.peek(3).unwrap(), b"abc");
+        assert_eq!(restored.serialize(), bytes);
+        let mut odd = bytes.clone();
+        odd[16..24].copy_from_slice(&3u64.to_le_bytes());
+        let restored = RingBuffer::<u8>::deserialize(&odd).unwrap();
+        assert_eq!(restored.capacity(), 3);
+        assert!(!restored.is_mirrored());
+
+        // Huge capacities are refused before allocating anything.
+        let mut empty = RingBuffer::<i16>::new(1).unwrap().serialize();
+        assert_eq!(empty.len(), HEADER_LEN);
+        for capacity in &[(MAX_STORAGE / 2 + 1) as u64, u64::MAX] {
+            empty[16..24].copy_from_slice(&capacity.to_le_bytes());
+            assert_eq!(
+                RingBuffer::<i16>::deserialize(&empty).err(),
+                Some(Error::InvalidArgument)
+            );
+        }
+    }
+}
@@ -0,0 +1,484 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A wait-free ring buffer for exactly one producer thread and one consumer thread.
+//!
+//! Both ends only coordinate through atomic positions, each of which is only ever written by
+//! one of them, so neither ever blocks or retries. The positions count every byte ever stored
+//! instead of wrapping around, which makes a full buffer distinguishable from an empty one.
+use crate::ffi::{slice, slice_mut, status};
+use crate::{Error, OverflowPolicy, Pushed, Status};
+use std::convert::TryFrom;
+use std::sync::atomic::{fence, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+
+struct Shared {
+    head: AtomicU64,
+    tail: AtomicU64,
+    oldest: AtomicU64,
+    data: Box<[AtomicU8]>,
+    policy: OverflowPolicy,
+}
+
+impl Shared {
+    fn ring(&self) -> Ring<'_> {
+        Ring {
+            head: &self.head,
+            tail: &self.tail,
+            oldest: &self.oldest,
+            data: &self.data,
+            policy: self.policy,
+        }
+    }
+}
+
+/// The positions and bytes of a single-producer single-consumer ring buffer, wherever they
+/// are stored.
+pub(crate) struct Ring<'a> {
+    // Position of the next byte to be consumed. Only advanced by the consumer.
+    pub head: &'a AtomicU64,
+    // Position of the next byte to be written. Only advanced by the producer.
+    pub tail: &'a AtomicU64,
+    // Position of the oldest byte which was not overwritten. Only advanced by the producer,
+    // which moves it past the `head` when overwriting bytes that were not consumed yet.
+    pub oldest: &'a AtomicU64,
+    // The bytes are atomic so that the consumer can safely read them while the producer
+    // overwrites them, in which case the consumer notices that `oldest` moved.
+    pub data: &'a [AtomicU8],
+    pub policy: OverflowPolicy,
+}
+
+impl Ring<'_> {
+    fn capacity(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn index(&self, position: u64) -> usize {
+        (position % self.capacity()) as usize
+    }
+
+    fn write(&self, position: u64, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let start = self.index(position);
+        let first = bytes.len().min(self.data.len() - start);
+        for (slot, byte) in self.data[start..start + first].iter().zip(bytes) {
+            slot.store(*byte, Ordering::Relaxed);
+        }
+        for (slot, byte) in self.data.iter().zip(&bytes[first..]) {
+            slot.store(*byte, Ordering::Relaxed);
+        }
+    }
+
+    fn read(&self, position: u64, dst: &mut [u8]) {
+        if dst.is_empty() {
+            return;
+        }
+        let start = self.index(position);
+        let first = dst.len().min(self.data.len() - start);
+        let (left, right) = dst.split_at_mut(first);
+        for (byte, slot) in left.iter_mut().zip(&self.data[start..]) {
+            *byte = slot.load(Ordering::Relaxed);
+        }
+        for (byte, slot) in right.iter_mut().zip(self.data.iter()) {
+            *byte = slot.load(Ordering::Relaxed);
+        }
+    }
+
+    /// Position of the oldest readable byte, as seen by the producer.
+    fn start(&self) -> u64 {
+        let head = self.head.load(Ordering::Acquire);
+        head.max(self.oldest.load(Ordering::Relaxed))
+    }
+
+    /// How many bytes can be pushed without overflowing? Only for the producer.
+    pub fn write_available(&self) -> usize {
+        let tail = self.tail.load(Ordering::Relaxed);
+        (self.capacity() - (tail - self.start())) as usize
+    }
+
+    /// Pushes data following the `OverflowPolicy`, just like `RingBuffer::push`. Only for
+    /// the producer.
+    ///
+    /// This never waits for the consumer. Overwritten bytes which the consumer was skipping
+    /// at the same time are counted as dropped anyway.
+    pub fn push(&self, bytes: &[u8]) -> Result<Pushed, Error> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let start = self.start();
+        let leeway = (self.capacity() - (tail - start)) as usize;
+        let mut evicted = 0;
+
+        let stored = if bytes.len() <= leeway {
+            bytes
+        } else {
+            match self.policy {
+                OverflowPolicy::OverwriteOldest => {
+                    let stored = &bytes[bytes.len().saturating_sub(self.data.len())..];
+
+                    // Tell the consumer that the bytes about to be overwritten are gone.
+                    let oldest = tail + stored.len() as u64 - self.capacity();
+                    evicted = (oldest - start) as usize;
+                    self.oldest.store(oldest, Ordering::Release);
+                    // A consumer which sees any of the bytes written below must also see
+                    // the new `oldest`.
+                    fence(Ordering::Release);
+                    stored
+                }
+                OverflowPolicy::Reject => {
+                    return Err(Error::Full {
+                        requested: bytes.len(),
+                        available: leeway,
+                    })
+                }
+                OverflowPolicy::TruncateIncoming => &bytes[..leeway],
+                OverflowPolicy::DropIncoming => &[],
+            }
+        };
+
+        self.write(tail, stored);
+        self.tail
+            .store(tail + stored.len() as u64, Ordering::Release);
+
+        Ok(Pushed {
+            stored: stored.len(),
+            dropped: bytes.len() - stored.len() + evicted,
+        })
+    }
+
+    /// How many bytes can be read? Only for the consumer.
+    pub fn read_available(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        // `oldest` is never past the `tail` loaded after it.
+        let oldest = self.oldest.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail - head.max(oldest)) as usize
+    }
+
+    /// Checks that the producer did not overwrite the byte at `head` or any later one, which
+    /// the consumer did not get to consume.
+    ///
+    /// Otherwise this fails with `Evicted` and moves the head to the oldest byte left, for
+    /// reading to resume from there.
+    fn check_head(&self, head: u64) -> Result<(), Error> {
+        let oldest = self.oldest.load(Ordering::Acquire);
+        if oldest > head {
+            self.head.store(oldest, Ordering::Release);
+            return Err(Error::Evicted {
+                requested: head,
+                oldest,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that `n` bytes can be read from `head`. Only for the consumer.
+    fn check_available(&self, head: u64, n: usize) -> Result<(), Error> {
+        self.check_head(head)?;
+        let available = (self.tail.load(Ordering::Acquire) - head) as usize;
+        if n > available {
+            return Err(Error::OutOfBounds {
+                requested: n,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Copies the oldest `dst.len()` bytes into `dst`, without consuming them. Only for the
+    /// consumer.
+    ///
+    /// Fails with `Evicted` if the producer overwrote unread bytes since the consumer last
+    /// peeked or skipped, or while copying them.
+    pub fn peek(&self, dst: &mut [u8]) -> Result<(), Error> {
+        let head = self.head.load(Ordering::Relaxed);
+        self.check_available(head, dst.len())?;
+        self.read(head, dst);
+
+        // If the producer overwrote what we were reading, it moved `oldest` first.
+        fence(Ordering::Acquire);
+        self.check_head(head)
+    }
+
+    /// Consumes the oldest `n` bytes. Only for the consumer.
+    ///
+    /// Fails with `Evicted`, without consuming anything, if the producer overwrote unread
+    /// bytes since the consumer last peeked or skipped.
+    pub fn skip(&self, n: usize) -> Result<(), Error> {
+        let head = self.head.load(Ordering::Relaxed);
+        self.check_available(head, n)?;
+        self.head.store(head + n as u64, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// The end of a single-producer single-consumer ring buffer which pushes data.
+///
+/// It may be used from a different thread than the `Consumer`, but only from one thread at a
+/// time.
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+/// The end of a single-producer single-consumer ring buffer which peeks and skips data.
+///
+/// It may be used from a different thread than the `Producer`, but only from one thread at a
+/// time.
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+/// Creates a wait-free ring buffer of the specified capacity and `OverflowPolicy`, for
+/// exactly one thread pushing and one thread peeking and skipping.
+///
+/// Fails with `AllocationFailed` if the memory cannot be allocated.
+pub fn channel(capacity: usize, policy: OverflowPolicy) -> Result<(Producer, Consumer), Error> {
+    let mut data = Vec::new();
+    data.try_reserve_exact(capacity)
+        .map_err(|_| Error::AllocationFailed)?;
+    data.resize_with(capacity, || AtomicU8::new(0));
+
+    let shared = Arc::new(Shared {
+        head: AtomicU64::new(0),
+        tail: AtomicU64::new(0),
+        oldest: AtomicU64::new(0),
+        data: data.into_boxed_slice(),
+        policy,
+    });
+    Ok((
+        Producer {
+            shared: Arc::clone(&shared),
+        },
+        Consumer { shared },
+    ))
+}
+
+impl Producer {
+    /// How many bytes can be pushed without overflowing?
+    pub fn write_available(&self) -> usize {
+        self.shared.ring().write_available()
+    }
+
+    /// Pushes data following the `OverflowPolicy`, just like `RingBuffer::push`.
+    ///
+    /// This never waits for the consumer.
+    pub fn push(&self, bytes: &[u8]) -> Result<Pushed, Error> {
+        self.shared.ring().push(bytes)
+    }
+}
+
+impl Consumer {
+    /// How many bytes can be read?
+    pub fn read_available(&self) -> usize {
+        self.shared.ring().read_available()
+    }
+
+    /// Copies the oldest `dst.len()` bytes into `dst`, without consuming them.
+    ///
+    /// Fails with `OutOfBounds` if one tries to read more than available, or with `Evicted`
+    /// if the producer overwrote bytes which were not consumed yet since the last `peek` or
+    /// `skip`. Reading then resumes from the oldest byte left.
+    pub fn peek(&self, dst: &mut [u8]) -> Result<(), Error> {
+        self.shared.ring().peek(dst)
+    }
+
+    /// Consumes the oldest `n` bytes.
+    ///
+    /// Fails just like `peek`, without consuming anything, so that bytes are never skipped
+    /// without having been peeked.
+    pub fn skip(&self, n: usize) -> Result<(), Error> {
+        self.shared.ring().skip(n)
+    }
+}
+
+/// Creates a single-producer single-consumer ring buffer of the specified capacity and
+/// `OverflowPolicy`, and stores its two ends in `producer` and `consumer`.
+///
+/// The producer and the consumer may be used from different threads at the same time, but
+/// each of them must only be used by one thread at a time. None of the `spsc_` functions
+/// ever block.
+///
+/// Fails with `InvalidArgument` if `policy` is not one of the `OverflowPolicy` values.
+///
+/// It is undefined behaviour to pass invalid pointers to `producer` or `consumer`.
+#[no_mangle]
+pub extern "C" fn spsc_new(
+    capacity: usize,
+    policy: u32,
+    producer: *mut *mut Producer,
+    consumer: *mut *mut Consumer,
+) -> Status {
+    status(|| {
+        let producer = unsafe { producer.as_mut() }.ok_or(Error::NullPointer)?;
+        let consumer = unsafe { consumer.as_mut() }.ok_or(Error::NullPointer)?;
+        let (tx, rx) = channel(capacity, OverflowPolicy::try_from(policy)?)?;
+        *producer = Box::into_raw(Box::new(tx));
+        *consumer = Box::into_raw(Box::new(rx));
+        Ok(())
+    })
+}
+
+/// How much data can be pushed without overflowing?
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `Producer`.
+#[no_mangle]
+pub extern "C" fn spsc_write_available(producer: *const Producer) -> usize {
+    let producer = unsafe { &*producer };
+    producer.write_available()
+}
+
+/// Pushes data to the buffer, storing how many bytes were stored and dropped in `out`
+/// (unless it's null).
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `Producer`,
+/// to pass an invalid pointer to bytes which is not of the matching length, or to pass an
+/// invalid non-null pointer to `out`.
+#[no_mangle]
+pub extern "C" fn spsc_push(
+    producer: *const Producer,
+    bytes: *const u8,
+    n: usize,
+    out: *mut Pushed,
+) -> Status {
+    status(|| {
+        let producer = unsafe { producer.as_ref() }.ok_or(Error::NullPointer)?;
+        let pushed = producer.push(slice(bytes, n)?)?;
+        if let Some(out) = unsafe { out.as_mut() } {
+            *out = pushed;
+        }
+        Ok(())
+    })
+}
+
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `Producer`.
+#[no_mangle]
+pub extern "C" fn spsc_producer_del(producer: *mut Producer) -> Status {
+    status(|| {
+        if producer.is_null() {
+            return Err(Error::NullPointer);
+        }
+        drop(unsafe { Box::from_raw(producer) });
+        Ok(())
+    })
+}
+
+/// How much data can be read from the buffer?
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `Consumer`.
+#[no_mangle]
+pub extern "C" fn spsc_read_available(consumer: *const Consumer) -> usize {
+    let consumer = unsafe { &*consumer };
+    consumer.read_available()
+}
+
+/// Peeks from the buffer, copying the oldest `n` bytes into `dst`.
+///
+/// Fails with `OutOfBounds` if one tries to read more than available in the buffer, or with
+/// `Evicted` if the producer overwrote bytes which were not consumed yet since the last peek
+/// or skip. Reading then resumes from the oldest byte left.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `Consumer`,
+/// or to pass an invalid pointer to `dst` which is not of the matching length.
+#[no_mangle]
+pub extern "C" fn spsc_peek(consumer: *const Consumer, dst: *mut u8, n: usize) -> Status {
+    status(|| {
+        let consumer = unsafe { consumer.as_ref() }.ok_or(Error::NullPointer)?;
+        consumer.peek(slice_mut(dst, n)?)
+    })
+}
+
+/// Skips data from the buffer.
+///
+/// Fails just like `spsc_peek`, without skipping anything.
+///
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `Consumer`.
+#[no_mangle]
+pub extern "C" fn spsc_skip(consumer: *const Consumer, n: usize) -> Status {
+    status(|| {
+        let consumer = unsafe { consumer.as_ref() }.ok_or(Error::NullPointer)?;
+        consumer.skip(n)
+    })
+}
+
+/// It is undefined behaviour to pass a pointer not pointing to a non-deleted `Consumer`.
+#[no_mangle]
+pub extern "C" fn spsc_consumer_del(consumer: *mut Consumer) -> Status {
+    status(|| {
+        if consumer.is_null() {
+            return Err(Error::NullPointer);
+        }
+        drop(unsafe { Box::from_raw(consumer) });
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn check_overwriting_producer() {
+        let (producer, consumer) = channel(16, OverflowPolicy::OverwriteOldest).unwrap();
+        let total = 100_000u32;
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..total {
+                    producer.push(&i.to_le_bytes()).unwrap();
+                }
+            });
+
+            // Whatever is read must be a run of consecutive values, since the producer only
+            // overwrites whole values in a buffer that is a multiple of their size.
+            let mut last = None;
+            while last != Some(total - 1) {
+                let mut bytes = [0; 4];
+                if consumer.read_available() < 4
+                    || consumer.peek(&mut bytes).is_err()
+                    || consumer.skip(4).is_err()
+                {
+                    continue;
+                }
+
+                let value = u32::from_le_bytes(bytes);
+                if let Some(last) = last {
+                    assert!(value > last);
+                }
+                last = Some(value);
+            }
+        });
+    }
+
+    #[test]
+    fn check_overwritten_skip() {
+        let (producer, consumer) = channel(4, OverflowPolicy::OverwriteOldest).unwrap();
+        producer.push(b"abcd").unwrap();
+        let mut bytes = [0; 2];
+        consumer.peek(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"ab");
+
+        // The bytes peeked were overwritten before being skipped, so nothing unseen is lost.
+        producer.push(b"ef").unwrap();
+        assert_eq!(
+            consumer.skip(2),
+            Err(Error::Evicted {
+                requested: 0,
+                oldest: 2
+            })
+        );
+        consumer.peek(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"cd");
+        consumer.skip(2).unwrap();
+
+        producer.push(b"ghij").unwrap();
+        assert!(consumer.peek(&mut bytes).is_err());
+        consumer.peek(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"gh");
+    }
+}
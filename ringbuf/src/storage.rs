@@ -0,0 +1,309 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::file::HEADER_LEN;
+use crate::Error;
+use std::io;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+
+/// Memory holding the elements of a `RingBuffer`.
+pub enum Storage<T> {
+    /// A single heap allocation of exactly `capacity` elements.
+    Heap(Box<[T]>),
+    /// The same memory mapped twice, back to back.
+    Mirrored(Mirror),
+    /// A memory-mapped file, where the elements follow a header of `file::HEADER_LEN` bytes.
+    File(Mapping),
+}
+
+impl<T: Copy + Default> Storage<T> {
+    pub fn heap(capacity: usize) -> Result<Self, Error> {
+        let mut storage = Vec::new();
+        storage
+            .try_reserve_exact(capacity)
+            .map_err(|_| Error::AllocationFailed)?;
+        storage.resize(capacity, T::default());
+
+        Ok(Storage::Heap(storage.into_boxed_slice()))
+    }
+
+    /// Number of elements the capacity of mirrored storage must be a multiple of.
+    pub fn mirror_granularity() -> usize {
+        let (mut a, mut b) = (Mirror::granularity(), mem::size_of::<T>());
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        Mirror::granularity() / a
+    }
+
+    /// Maps mirrored storage for `capacity` elements, which must be a multiple of
+    /// `mirror_granularity`.
+    pub fn mirrored(capacity: usize) -> io::Result<Self> {
+        let len = capacity
+            .checked_mul(mem::size_of::<T>())
+            .ok_or(io::ErrorKind::InvalidInput)?;
+        let mirror = Mirror::new(len)?;
+
+        // The memory starts zeroed, which is not necessarily a valid `T`.
+        let ptr = mirror.ptr.cast::<T>();
+        for i in 0..capacity {
+            unsafe { ptr.add(i).write(T::default()) };
+        }
+        Ok(Storage::Mirrored(mirror))
+    }
+}
+
+/// Cloning mirrored storage maps new memory, or allocates heap storage if that fails. Clones
+/// of file storage are on the heap, rather than sharing the file.
+impl<T: Copy + Default> Clone for Storage<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Storage::Heap(heap) => Storage::Heap(heap.clone()),
+            Storage::File(_) => Storage::Heap(self.to_vec().into_boxed_slice()),
+            Storage::Mirrored(_) => match Storage::mirrored(self.len()) {
+                Ok(mut storage) => {
+                    storage.copy_from_slice(self);
+                    storage
+                }
+                Err(_) => Storage::Heap(self.to_vec().into_boxed_slice()),
+            },
+        }
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn is_mirrored(&self) -> bool {
+        matches!(self, Storage::Mirrored(_))
+    }
+
+    pub fn is_file(&self) -> bool {
+        matches!(self, Storage::File(_))
+    }
+
+    /// Returns the memory through which ranges can be accessed.
+    ///
+    /// For mirrored storage this is twice the capacity long, so that any range of up to
+    /// `capacity` elements starting inside the storage can be accessed without wrapping.
+    pub fn window(&self) -> &[T] {
+        match self {
+            Storage::Heap(_) | Storage::File(_) => self,
+            Storage::Mirrored(mirror) => unsafe {
+                std::slice::from_raw_parts(mirror.ptr.cast(), 2 * mirror.len / mem::size_of::<T>())
+            },
+        }
+    }
+}
+
+impl<T> Deref for Storage<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Storage::Heap(heap) => heap,
+            Storage::Mirrored(mirror) => unsafe {
+                std::slice::from_raw_parts(mirror.ptr.cast(), mirror.len / mem::size_of::<T>())
+            },
+            Storage::File(mapping) => unsafe {
+                std::slice::from_raw_parts(
+                    mapping.ptr.add(HEADER_LEN).cast(),
+                    (mapping.len - HEADER_LEN) / mem::size_of::<T>(),
+                )
+            },
+        }
+    }
+}
+
+impl<T> DerefMut for Storage<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match self {
+            Storage::Heap(heap) => heap,
+            Storage::Mirrored(mirror) => unsafe {
+                std::slice::from_raw_parts_mut(mirror.ptr.cast(), mirror.len / mem::size_of::<T>())
+            },
+            Storage::File(mapping) => unsafe {
+                std::slice::from_raw_parts_mut(
+                    mapping.ptr.add(HEADER_LEN).cast(),
+                    (mapping.len - HEADER_LEN) / mem::size_of::<T>(),
+                )
+            },
+        }
+    }
+}
+
+/// A memory file of `len` bytes mapped twice in a row, so that writing to any byte also
+/// changes the byte `len` positions after (or before) it.
+pub struct Mirror {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// The mapping is owned exclusively, just like a `Box<[u8]>` would be.
+unsafe impl Send for Mirror {}
+unsafe impl Sync for Mirror {}
+
+#[cfg(target_os = "linux")]
+impl Mirror {
+    /// Granularity of the length of a mirror.
+    pub fn granularity() -> usize {
+        crate::sys::page_size()
+    }
+
+    /// Maps a new mirror of `len` bytes, which must be a non-zero multiple of the page size.
+    pub fn new(len: usize) -> io::Result<Self> {
+        use crate::sys::*;
+        use std::fs::File;
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+        use std::ptr;
+
+        if len == 0 || len % Self::granularity() != 0 {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let double = len.checked_mul(2).ok_or(io::ErrorKind::InvalidInput)?;
+
+        let fd = unsafe { memfd_create(b"ringbuf\0".as_ptr().cast(), MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // The file is closed once mapped, the mappings keep the memory alive.
+        let file = unsafe { File::from_raw_fd(fd) };
+        file.set_len(len as u64)?;
+
+        // Reserve the whole range by mapping the file over it (the second half lies past the
+        // end of the file), and then map the file again over the second half.
+        let prot = PROT_READ | PROT_WRITE;
+        let first = unsafe { mmap(ptr::null_mut(), double, prot, MAP_SHARED, fd, 0) };
+        if first == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let second = unsafe {
+            mmap(
+                first.cast::<u8>().add(len).cast(),
+                len,
+                prot,
+                MAP_SHARED | MAP_FIXED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if second == MAP_FAILED {
+            let error = io::Error::last_os_error();
+            unsafe { munmap(first, double) };
+            return Err(error);
+        }
+
+        Ok(Mirror {
+            ptr: first.cast(),
+            len,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        unsafe { crate::sys::munmap(self.ptr.cast(), 2 * self.len) };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Mirror {
+    pub fn granularity() -> usize {
+        1
+    }
+
+    pub fn new(_len: usize) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// The first `len` bytes of a file mapped into memory, shared with every other mapping of the
+/// same file, including those of other processes.
+pub struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+    file: std::fs::File,
+}
+
+// The mapping is only accessed through atomics or exclusive references.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Borrows the mapped bytes.
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Mapping {
+    /// Maps the first `len` bytes of `file`, which must be opened for reading and writing.
+    pub fn new(file: &std::fs::File, len: usize) -> io::Result<Self> {
+        use crate::sys::*;
+        use std::os::unix::io::AsRawFd;
+
+        if len == 0 {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        // Kept open for `flush`.
+        let file = file.try_clone()?;
+        let prot = PROT_READ | PROT_WRITE;
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                prot,
+                MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr.cast(),
+            len,
+            file,
+        })
+    }
+
+    /// Writes the mapped bytes back to the file, and waits for them to reach the disk.
+    ///
+    /// The bytes are shared with the page cache of the file, so syncing the file is the same as
+    /// `msync`, without the flags whose values depend on the architecture.
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { crate::sys::munmap(self.ptr.cast(), self.len) };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Mapping {
+    pub fn new(_file: &std::fs::File, _len: usize) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
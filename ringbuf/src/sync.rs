@@ -0,0 +1,227 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::{Error, RingBuffer};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+struct State<T> {
+    buffer: RingBuffer<T>,
+    shutdown: bool,
+}
+
+/// A `RingBuffer` which can be shared between threads, such as a producer pushing and a
+/// consumer peeking and skipping.
+pub struct SyncRingBuffer<T = u8> {
+    state: Mutex<State<T>>,
+    // Notified whenever the buffer is modified or shut down.
+    changed: Condvar,
+}
+
+/// Exclusive access to the `RingBuffer` inside a `SyncRingBuffer`.
+///
+/// Threads waiting on the buffer are woken up when this is dropped, if it was used to modify
+/// the buffer.
+pub struct Guard<'a, T> {
+    state: MutexGuard<'a, State<T>>,
+    changed: &'a Condvar,
+    modified: bool,
+}
+
+impl<T> Deref for Guard<'_, T> {
+    type Target = RingBuffer<T>;
+
+    fn deref(&self) -> &RingBuffer<T> {
+        &self.state.buffer
+    }
+}
+
+impl<T> DerefMut for Guard<'_, T> {
+    fn deref_mut(&mut self) -> &mut RingBuffer<T> {
+        self.modified = true;
+        &mut self.state.buffer
+    }
+}
+
+impl<T> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        if self.modified {
+            self.changed.notify_all();
+        }
+    }
+}
+
+impl<T: Copy> SyncRingBuffer<T> {
+    /// Wraps `buffer` so that it can be shared between threads, for example in an `Arc`.
+    pub fn new(buffer: RingBuffer<T>) -> Self {
+        SyncRingBuffer {
+            state: Mutex::new(State {
+                buffer,
+                shutdown: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, State<T>> {
+        // None of the operations can leave the buffer in an inconsistent state, so the
+        // contents can still be used if another thread panicked while holding the lock.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the buffer for exclusive access from the calling thread, until the returned
+    /// `Guard` is dropped.
+    pub fn lock(&self) -> Guard<'_, T> {
+        Guard {
+            state: self.lock_state(),
+            changed: &self.changed,
+            modified: false,
+        }
+    }
+
+    /// Blocks until `ready` returns true for the buffer, the timeout expires, or the buffer
+    /// is shut down (whichever happens first). Waits forever if `timeout` is `None`.
+    fn wait_until<F>(&self, timeout: Option<Duration>, ready: F) -> Result<(), Error>
+    where
+        F: Fn(&RingBuffer<T>) -> bool,
+    {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.lock_state();
+        loop {
+            if ready(&state.buffer) {
+                return Ok(());
+            }
+            if state.shutdown {
+                return Err(Error::Shutdown);
+            }
+
+            state = match deadline {
+                None => self
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::Timeout);
+                    }
+                    self.changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+    }
+
+    /// Blocks until at least `n` elements can be read, and returns how many can be read.
+    ///
+    /// Fails with `Error::Timeout` if that doesn't happen in time, or with `Error::Shutdown`
+    /// if the buffer is shut down first. Data already in the buffer can still be waited for
+    /// after shutting down.
+    pub fn wait_readable(&self, n: usize, timeout: Option<Duration>) -> Result<usize, Error> {
+        self.check_satisfiable(n)?;
+        self.wait_until(timeout, |buffer| buffer.len >= n)?;
+        Ok(self.lock().len)
+    }
+
+    /// Blocks until at least `n` elements can be written without overflowing, and returns
+    /// how many can be written.
+    ///
+    /// Fails just like `wait_readable`.
+    pub fn wait_writable(&self, n: usize, timeout: Option<Duration>) -> Result<usize, Error> {
+        self.check_satisfiable(n)?;
+        self.wait_until(timeout, |buffer| buffer.capacity() - buffer.len >= n)?;
+        let buffer = self.lock();
+        Ok(buffer.capacity() - buffer.len)
+    }
+
+    fn check_satisfiable(&self, n: usize) -> Result<(), Error> {
+        let capacity = self.lock().capacity();
+        if n > capacity {
+            Err(Error::OutOfBounds {
+                requested: n,
+                available: capacity,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wakes up every thread waiting on the buffer, and makes any further waits fail
+    /// instead of blocking.
+    pub fn shutdown(&self) {
+        self.lock_state().shutdown = true;
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OverflowPolicy;
+    use std::thread;
+
+    #[test]
+    fn check_producer_consumer() {
+        let buffer =
+            SyncRingBuffer::new(RingBuffer::with_policy(16, OverflowPolicy::Reject).unwrap());
+        let total = 1000;
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut next = 0;
+                while next < total {
+                    if buffer.lock().push(&[next as u8]).is_ok() {
+                        next += 1;
+                    }
+                }
+            });
+
+            let mut received = 0;
+            while received < total {
+                let mut byte = [0];
+                let mut buffer = buffer.lock();
+                if buffer.copy_out(&mut byte).is_ok() {
+                    buffer.skip(1).unwrap();
+                    assert_eq!(byte[0], received as u8);
+                    received += 1;
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn check_waits() {
+        let buffer =
+            SyncRingBuffer::new(RingBuffer::with_policy(4, OverflowPolicy::Reject).unwrap());
+        let short = Some(Duration::from_millis(10));
+
+        assert_eq!(buffer.wait_readable(1, short), Err(Error::Timeout));
+        assert!(matches!(
+            buffer.wait_readable(5, None),
+            Err(Error::OutOfBounds { .. })
+        ));
+        assert_eq!(buffer.wait_writable(4, None), Ok(4));
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                buffer.lock().push(&[1, 2, 3]).unwrap();
+            });
+            assert_eq!(buffer.wait_readable(3, None), Ok(3));
+        });
+        assert_eq!(buffer.wait_writable(2, short), Err(Error::Timeout));
+
+        thread::scope(|scope| {
+            scope.spawn(|| buffer.shutdown());
+            assert_eq!(buffer.wait_writable(2, None), Err(Error::Shutdown));
+        });
+        // Data that is already there can still be waited for.
+        assert_eq!(buffer.wait_readable(3, short), Ok(3));
+    }
+}
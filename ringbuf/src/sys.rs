@@ -0,0 +1,41 @@
+// Copyright 2021 - SupportFactory.net
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The few Linux system calls needed for memory mapping, declared by hand to avoid pulling in
+//! any dependency. The constants have the generic values, which are used by every Linux
+//! architecture Rust supports (only alpha and parisc number some of them differently).
+#![cfg(target_os = "linux")]
+
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_void};
+
+pub const PROT_READ: c_int = 0x1;
+pub const PROT_WRITE: c_int = 0x2;
+pub const MAP_SHARED: c_int = 0x01;
+pub const MAP_FIXED: c_int = 0x10;
+pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+pub const MFD_CLOEXEC: c_uint = 0x1;
+pub const SC_PAGESIZE: c_int = 30;
+
+extern "C" {
+    pub fn memfd_create(name: *const c_char, flags: c_uint) -> c_int;
+    pub fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: c_long,
+    ) -> *mut c_void;
+    pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    pub fn sysconf(name: c_int) -> c_long;
+}
+
+/// Size of a memory page, which mappings are aligned to.
+pub fn page_size() -> usize {
+    unsafe { sysconf(SC_PAGESIZE) as usize }
+}